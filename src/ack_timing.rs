@@ -0,0 +1,77 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Address acknowledge timing statistics.
+///
+/// The times are measured in ticks of the clock passed to
+/// [`I2CDrv::set_ack_clock`](crate::I2CDrv::set_ack_clock), from setting the
+/// CR1.START bit to the end of the address transmission. A growing
+/// [`max`](I2CAckStats::max) or [`last`](I2CAckStats::last) value can indicate
+/// a degrading device or bus before it fails completely.
+#[derive(Clone, Copy, Default)]
+pub struct I2CAckStats {
+    /// Number of acknowledged addresses.
+    pub count: u32,
+    /// Shortest acknowledge time.
+    pub min: u32,
+    /// Longest acknowledge time.
+    pub max: u32,
+    /// Most recent acknowledge time.
+    pub last: u32,
+}
+
+pub(crate) struct AckTiming {
+    start: AtomicU32,
+    count: AtomicU32,
+    min: AtomicU32,
+    max: AtomicU32,
+    last: AtomicU32,
+}
+
+impl AckTiming {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            start: AtomicU32::new(0),
+            count: AtomicU32::new(0),
+            min: AtomicU32::new(u32::MAX),
+            max: AtomicU32::new(0),
+            last: AtomicU32::new(0),
+        })
+    }
+
+    pub(crate) fn start(&self, clock: Option<fn() -> u32>) {
+        if let Some(clock) = clock {
+            self.start.store(clock(), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn ack(&self, clock: Option<fn() -> u32>) {
+        if let Some(clock) = clock {
+            let time = clock().wrapping_sub(self.start.load(Ordering::Relaxed));
+            self.last.store(time, Ordering::Relaxed);
+            self.min.fetch_min(time, Ordering::Relaxed);
+            self.max.fetch_max(time, Ordering::Relaxed);
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> I2CAckStats {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return I2CAckStats::default();
+        }
+        I2CAckStats {
+            count,
+            min: self.min.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+            last: self.last.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.min.store(u32::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+        self.last.store(0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,46 @@
+/// I²C slave address.
+///
+/// Accepted by [`I2CMaster::write`](crate::I2CMaster::write) and
+/// [`I2CMaster::read`](crate::I2CMaster::read). A plain `u8` converts into a
+/// 7-bit address, so the existing calls don't need to change. Other
+/// operations take 7-bit addresses only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CAddr {
+    /// 7-bit address.
+    Bit7(u8),
+    /// 10-bit address.
+    ///
+    /// The address is sent as a two-byte sequence: the `11110xx0` header with
+    /// the two most significant address bits, followed by the eight least
+    /// significant bits. A read sends the sequence with the write direction,
+    /// and then a repeated Start with the `11110xx1` header.
+    Bit10(u16),
+}
+
+impl From<u8> for I2CAddr {
+    fn from(addr: u8) -> Self {
+        Self::Bit7(addr)
+    }
+}
+
+impl I2CAddr {
+    /// Returns the first address byte with the direction bit, and the second
+    /// address byte for 10-bit addresses.
+    pub(crate) fn bytes(self, read: bool) -> (u8, Option<u8>) {
+        match self {
+            Self::Bit7(addr) => (addr << 1 | u8::from(read), None),
+            Self::Bit10(addr) => {
+                (0xF0 | (addr >> 7) as u8 & 0b110 | u8::from(read), Some(addr as u8))
+            }
+        }
+    }
+
+    /// Returns the 7-bit address for the dry-run record.
+    #[cfg(feature = "dry-run")]
+    pub(crate) fn bit7(self) -> u8 {
+        match self {
+            Self::Bit7(addr) => addr,
+            Self::Bit10(_) => panic!("10-bit address in dry-run mode"),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{diverged::I2CDiverged, I2CError};
+use drone_cortexm::reg::prelude::*;
+use drone_stm32_map::periph::i2c::{traits::*, I2CMap};
+
+/// Writes `buf_tx` to the slave at the address `addr` by polling the status
+/// flags, and sends the Stop signal.
+pub(crate) fn write_blocking<I2C: I2CMap>(
+    i2c: &I2CDiverged<I2C>,
+    watchdog: fn(),
+    addr: u8,
+    buf_tx: &[u8],
+) -> Result<(), I2CError> {
+    let cr2_val = begin(i2c, watchdog);
+    let result = (|| {
+        i2c.i2c_cr1.modify_reg(|r, v| {
+            r.ack().clear(v); // acknowledge disable
+            r.pos().clear(v); // ACK bit controls the current byte
+            r.start().set(v); // start generation
+        });
+        wait(i2c, watchdog, |sr1, val| sr1.sb().read(val))?;
+        i2c.i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1))); // 8-bit data register
+        wait(i2c, watchdog, |sr1, val| sr1.addr().read(val))?;
+        i2c.i2c_sr2.load_val(); // reading SR2 after SR1 clears ADDR
+        for (sent, &byte) in buf_tx.iter().enumerate() {
+            wait(i2c, watchdog, |sr1, val| sr1.tx_e().read(val)).map_err(|e| nack(i2c, e, sent))?;
+            i2c.i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(byte))); // 8-bit data register
+        }
+        if !buf_tx.is_empty() {
+            wait(i2c, watchdog, |sr1, val| sr1.btf().read(val))
+                .map_err(|e| nack(i2c, e, buf_tx.len()))?;
+        }
+        i2c.i2c_cr1.stop().set_bit(); // stop generation
+        Ok(())
+    })();
+    end(i2c, cr2_val);
+    result
+}
+
+/// Reads from the slave at the address `addr` into `buf_rx` by polling the
+/// status flags, following the master receiver sequences of the reference
+/// manual, and sends the Stop signal.
+pub(crate) fn read_blocking<I2C: I2CMap>(
+    i2c: &I2CDiverged<I2C>,
+    watchdog: fn(),
+    addr: u8,
+    buf_rx: &mut [u8],
+) -> Result<(), I2CError> {
+    assert!(!buf_rx.is_empty(), "Empty blocking read");
+    let cr2_val = begin(i2c, watchdog);
+    let len = buf_rx.len();
+    let result = (|| {
+        i2c.i2c_cr1.modify_reg(|r, v| {
+            match len {
+                1 => {
+                    r.ack().clear(v); // acknowledge disable
+                    r.pos().clear(v); // ACK bit controls the current byte
+                }
+                2 => {
+                    r.ack().set(v); // acknowledge enable
+                    r.pos().set(v); // ACK bit controls the next byte
+                }
+                _ => {
+                    r.ack().set(v); // acknowledge enable
+                    r.pos().clear(v); // ACK bit controls the current byte
+                }
+            }
+            r.start().set(v); // start generation
+        });
+        wait(i2c, watchdog, |sr1, val| sr1.sb().read(val))?;
+        i2c.i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1 | 1))); // 8-bit data register
+        wait(i2c, watchdog, |sr1, val| sr1.addr().read(val))?;
+        match len {
+            1 => {
+                i2c.i2c_sr2.load_val(); // reading SR2 after SR1 clears ADDR
+                i2c.i2c_cr1.stop().set_bit(); // stop generation
+                wait(i2c, watchdog, |sr1, val| sr1.rx_ne().read(val))?;
+                buf_rx[0] = read_dr(i2c);
+            }
+            2 => {
+                i2c.i2c_sr2.load_val(); // reading SR2 after SR1 clears ADDR
+                i2c.i2c_cr1.ack().clear_bit(); // acknowledge disable
+                wait(i2c, watchdog, |sr1, val| sr1.btf().read(val))?;
+                i2c.i2c_cr1.stop().set_bit(); // stop generation
+                buf_rx[0] = read_dr(i2c);
+                buf_rx[1] = read_dr(i2c);
+            }
+            _ => {
+                i2c.i2c_sr2.load_val(); // reading SR2 after SR1 clears ADDR
+                let (head, tail) = buf_rx.split_at_mut(len - 3);
+                for byte in head {
+                    wait(i2c, watchdog, |sr1, val| sr1.rx_ne().read(val))?;
+                    *byte = read_dr(i2c);
+                }
+                // byte N-2 is in DR, and byte N-1 is in the shift register
+                wait(i2c, watchdog, |sr1, val| sr1.btf().read(val))?;
+                i2c.i2c_cr1.ack().clear_bit(); // acknowledge disable
+                tail[0] = read_dr(i2c);
+                wait(i2c, watchdog, |sr1, val| sr1.btf().read(val))?;
+                i2c.i2c_cr1.stop().set_bit(); // stop generation
+                tail[1] = read_dr(i2c);
+                wait(i2c, watchdog, |sr1, val| sr1.rx_ne().read(val))?;
+                tail[2] = read_dr(i2c);
+            }
+        }
+        Ok(())
+    })();
+    end(i2c, cr2_val);
+    result
+}
+
+/// Waits for the previous Stop signal generation, and disables the interrupts
+/// and the DMA requests. Returns the previous CR2 value to be restored.
+fn begin<I2C: I2CMap>(i2c: &I2CDiverged<I2C>, watchdog: fn()) -> I2C::I2CCr2Val {
+    while i2c.i2c_cr1.stop().read_bit() {
+        // stop generation
+        watchdog();
+    }
+    let cr2_val = i2c.i2c_cr2.load_val();
+    i2c.i2c_cr2.modify_reg(|r, v| {
+        r.itbufen().clear(v); // buffer interrupt disable
+        r.itevten().clear(v); // event interrupt disable
+        r.iterren().clear(v); // error interrupt disable
+        r.dmaen().clear(v); // DMA requests disable
+    });
+    cr2_val
+}
+
+/// Restores the CR2 value saved by [`begin`].
+fn end<I2C: I2CMap>(i2c: &I2CDiverged<I2C>, cr2_val: I2C::I2CCr2Val) {
+    i2c.i2c_cr1.pos().clear_bit(); // ACK bit controls the current byte
+    i2c.i2c_cr2.store_val(cr2_val);
+}
+
+/// Polls SR1 until `flag` is set. If an error flag is set, clears the error
+/// flags, releases the bus, and returns the error.
+fn wait<I2C: I2CMap>(
+    i2c: &I2CDiverged<I2C>,
+    watchdog: fn(),
+    flag: impl Fn(I2C::CI2CSr1, &I2C::I2CSr1Val) -> bool,
+) -> Result<(), I2CError> {
+    loop {
+        let val = i2c.i2c_sr1.load_val();
+        if let Some(error) = I2CError::load::<I2C>(&val, i2c.i2c_sr1) {
+            i2c.i2c_sr1.modify_reg(|r, v| {
+                r.berr().clear(v); // clear misplaced Start or Stop condition
+                r.arlo().clear(v); // clear arbitration lost
+                r.af().clear(v); // clear acknowledge failure
+                r.ovr().clear(v); // clear overrun/underrun
+                r.timeout().clear(v); // clear timeout or Tlow error
+                r.pecerr().clear(v); // clear PEC error in reception
+            });
+            if error != I2CError::ArbitrationLost {
+                // on arbitration loss the bus is released by the hardware
+                i2c.i2c_cr1.stop().set_bit(); // stop generation
+            }
+            return Err(error);
+        }
+        if flag(i2c.i2c_sr1, &val) {
+            return Ok(());
+        }
+        watchdog();
+    }
+}
+
+/// Fills in the number of acknowledged bytes of an error returned by [`wait`],
+/// given the number of bytes `sent` to the data register.
+fn nack<I2C: I2CMap>(i2c: &I2CDiverged<I2C>, error: I2CError, sent: usize) -> I2CError {
+    error.with_sent(sent, i2c.i2c_sr1.tx_e().read_bit())
+}
+
+fn read_dr<I2C: I2CMap>(i2c: &I2CDiverged<I2C>) -> u8 {
+    i2c.i2c_dr.dr().read_bits() as u8 // 8-bit data register
+}
@@ -1,13 +1,14 @@
+use drone_core::token::Token;
 use drone_cortexm::reg::prelude::*;
 use drone_stm32_map::periph::dma::ch::{DmaChMap, DmaChPeriph};
 
 #[allow(dead_code)]
 pub(crate) struct DmaChDiverged<T: DmaChMap> {
-    pub(crate) dma_ccr: T::UDmaCcr,
+    pub(crate) dma_ccr: T::CDmaCcr,
     pub(crate) dma_cfcr: T::UDmaCfcr,
-    pub(crate) dma_cm0ar: T::UDmaCm0Ar,
+    pub(crate) dma_cm0ar: T::CDmaCm0Ar,
     pub(crate) dma_cm1ar: T::UDmaCm1Ar,
-    pub(crate) dma_cndtr: T::UDmaCndtr,
+    pub(crate) dma_cndtr: T::CDmaCndtr,
     pub(crate) dma_cpar: T::UDmaCpar,
     pub(crate) dma_ifcr_cdmeif: T::CDmaIfcrCdmeif,
     pub(crate) dma_ifcr_cfeif: T::CDmaIfcrCfeif,
@@ -42,11 +43,11 @@ impl<T: DmaChMap> From<DmaChPeriph<T>> for DmaChDiverged<T> {
             dma_isr_teif,
         } = periph;
         Self {
-            dma_ccr: dma_ccr.into_unsync(),
+            dma_ccr: dma_ccr.into_copy(),
             dma_cfcr: dma_cfcr.into_unsync(),
-            dma_cm0ar: dma_cm0ar.into_unsync(),
+            dma_cm0ar: dma_cm0ar.into_copy(),
             dma_cm1ar: dma_cm1ar.into_unsync(),
-            dma_cndtr: dma_cndtr.into_unsync(),
+            dma_cndtr: dma_cndtr.into_copy(),
             dma_cpar: dma_cpar.into_unsync(),
             dma_ifcr_cdmeif: dma_ifcr_cdmeif.into_copy(),
             dma_ifcr_cfeif: dma_ifcr_cfeif.into_copy(),
@@ -61,3 +62,31 @@ impl<T: DmaChMap> From<DmaChPeriph<T>> for DmaChDiverged<T> {
         }
     }
 }
+
+impl<T: DmaChMap> From<DmaChDiverged<T>> for DmaChPeriph<T> {
+    fn from(diverged: DmaChDiverged<T>) -> Self {
+        let DmaChDiverged { dma_cfcr, dma_cm1ar, dma_cpar, .. } = diverged;
+        // the copyable tokens are consumed with the diverged struct, and the
+        // copies held by the interrupt fibers are detached by the driver
+        unsafe {
+            Self {
+                dma_ccr: T::SDmaCcr::take(),
+                dma_cfcr: dma_cfcr.into_sync(),
+                dma_cm0ar: T::SDmaCm0Ar::take(),
+                dma_cm1ar: dma_cm1ar.into_sync(),
+                dma_cndtr: T::SDmaCndtr::take(),
+                dma_cpar: dma_cpar.into_sync(),
+                dma_ifcr_cdmeif: T::SDmaIfcrCdmeif::take(),
+                dma_ifcr_cfeif: T::SDmaIfcrCfeif::take(),
+                dma_ifcr_chtif: T::SDmaIfcrChtif::take(),
+                dma_ifcr_ctcif: T::SDmaIfcrCtcif::take(),
+                dma_ifcr_cteif: T::SDmaIfcrCteif::take(),
+                dma_isr_dmeif: T::SDmaIsrDmeif::take(),
+                dma_isr_feif: T::SDmaIsrFeif::take(),
+                dma_isr_htif: T::SDmaIsrHtif::take(),
+                dma_isr_tcif: T::SDmaIsrTcif::take(),
+                dma_isr_teif: T::SDmaIsrTeif::take(),
+            }
+        }
+    }
+}
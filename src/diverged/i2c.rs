@@ -1,3 +1,4 @@
+use drone_core::token::Token;
 use drone_cortexm::reg::prelude::*;
 use drone_stm32_map::periph::i2c::{I2CMap, I2CPeriph};
 
@@ -52,3 +53,42 @@ impl<T: I2CMap> From<I2CPeriph<T>> for I2CDiverged<T> {
         }
     }
 }
+
+impl<T: I2CMap> From<I2CDiverged<T>> for I2CPeriph<T> {
+    fn from(diverged: I2CDiverged<T>) -> Self {
+        let I2CDiverged {
+            rcc_busenr_i2cen,
+            rcc_busrstr_i2crst,
+            rcc_bussmenr_i2csmen,
+            i2c_cr1: _,
+            i2c_cr2: _,
+            i2c_oar1,
+            i2c_oar2,
+            i2c_dr: _,
+            i2c_sr1: _,
+            i2c_sr2: _,
+            i2c_ccr,
+            i2c_trise,
+            i2c_fltr,
+        } = diverged;
+        // the copyable tokens are consumed with the diverged struct, and the
+        // copies held by the interrupt fibers are detached by the driver
+        unsafe {
+            Self {
+                rcc_busenr_i2cen,
+                rcc_busrstr_i2crst,
+                rcc_bussmenr_i2csmen,
+                i2c_cr1: T::SI2CCr1::take(),
+                i2c_cr2: T::SI2CCr2::take(),
+                i2c_oar1: i2c_oar1.into_sync(),
+                i2c_oar2: i2c_oar2.into_sync(),
+                i2c_dr: T::SI2CDr::take(),
+                i2c_sr1: T::SI2CSr1::take(),
+                i2c_sr2: T::SI2CSr2::take(),
+                i2c_ccr: i2c_ccr.into_sync(),
+                i2c_trise: i2c_trise.into_sync(),
+                i2c_fltr: i2c_fltr.into_sync(),
+            }
+        }
+    }
+}
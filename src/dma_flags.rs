@@ -0,0 +1,62 @@
+use crate::diverged::DmaChDiverged;
+use drone_cortexm::reg::prelude::*;
+use drone_stm32_map::periph::dma::ch::DmaChMap;
+
+/// Interrupt flags of the both DMA streams used by the driver.
+///
+/// Returned by [`I2CDrv::dma_flags`](crate::I2CDrv::dma_flags) for diagnostic
+/// purposes.
+#[derive(Clone, Copy, Default)]
+pub struct I2CDmaFlags {
+    /// Flags of the DMA Tx stream.
+    pub tx: I2CDmaChFlags,
+    /// Flags of the DMA Rx stream.
+    pub rx: I2CDmaChFlags,
+}
+
+/// Interrupt flags of a single DMA stream.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, Default)]
+pub struct I2CDmaChFlags {
+    /// Transfer complete interrupt flag (DMA_xISR.TCIFx).
+    pub transfer_complete: bool,
+    /// Half transfer interrupt flag (DMA_xISR.HTIFx).
+    pub half_transfer: bool,
+    /// Transfer error interrupt flag (DMA_xISR.TEIFx).
+    pub transfer_error: bool,
+    /// Direct mode error interrupt flag (DMA_xISR.DMEIFx).
+    pub direct_mode_error: bool,
+    /// FIFO error interrupt flag (DMA_xISR.FEIFx).
+    pub fifo_error: bool,
+}
+
+impl I2CDmaChFlags {
+    pub(crate) fn load<T: DmaChMap>(dma: &DmaChDiverged<T>) -> Self {
+        let val = dma.dma_isr_tcif.load_val();
+        Self {
+            transfer_complete: dma.dma_isr_tcif.read(&val),
+            half_transfer: dma.dma_isr_htif.read(&val),
+            transfer_error: dma.dma_isr_teif.read(&val),
+            direct_mode_error: dma.dma_isr_dmeif.read(&val),
+            fifo_error: dma.dma_isr_feif.read(&val),
+        }
+    }
+
+    pub(crate) fn clear<T: DmaChMap>(self, dma: &DmaChDiverged<T>) {
+        if self.transfer_complete {
+            dma.dma_ifcr_ctcif.set_bit(); // clear transfer complete interrupt flag
+        }
+        if self.half_transfer {
+            dma.dma_ifcr_chtif.set_bit(); // clear half transfer interrupt flag
+        }
+        if self.transfer_error {
+            dma.dma_ifcr_cteif.set_bit(); // clear transfer error interrupt flag
+        }
+        if self.direct_mode_error {
+            dma.dma_ifcr_cdmeif.set_bit(); // clear direct mode error interrupt flag
+        }
+        if self.fifo_error {
+            dma.dma_ifcr_cfeif.set_bit(); // clear FIFO error interrupt flag
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use alloc::{boxed::Box, vec::Vec};
+
+/// A buffer container, which can be safely used as the buffer of a master
+/// session created by [`I2CDrv::master_with`](crate::I2CDrv::master_with).
+///
+/// The DMA and the event interrupt handler access the memory behind the
+/// container while an operation is in progress. If the session is leaked, e.g.
+/// with [`core::mem::forget`], the operation is aborted only by the next use
+/// of the driver, so the memory must stay valid and unused until then.
+///
+/// # Safety
+///
+/// The [`AsRef`] and [`AsMut`] implementations must return the same slice
+/// every time, and the memory of the slice must not be freed or reused if the
+/// container is leaked. Heap-allocated containers, such as [`Box`] and
+/// [`Vec`], and `'static` borrows satisfy this. Inline containers, such as
+/// arrays, don't, because their memory can be reused after the leak.
+pub unsafe trait DmaSafe: AsRef<[u8]> + AsMut<[u8]> {}
+
+unsafe impl DmaSafe for Box<[u8]> {}
+
+unsafe impl DmaSafe for Vec<u8> {}
+
+unsafe impl DmaSafe for &'static mut [u8] {}
+
+unsafe impl<const N: usize> DmaSafe for &'static mut [u8; N] {}
@@ -1,15 +1,42 @@
+#[cfg(feature = "ack-timing")]
+use crate::{ack_timing::AckTiming, I2CAckStats};
 use crate::{
+    blocking,
     diverged::{DmaChDiverged, I2CDiverged},
-    I2CMaster,
+    error::{ErrorSlot, I2CConfigError, I2CErrorKind},
+    fsm::{ev_transition, I2CEvAction, I2CEvFlags, I2CEvState},
+    int_drv::{read_int, read_reg_int, write_int},
+    master::timed,
+    nack::NackCounts,
+    DmaSafe, I2CAddr, I2CDmaChFlags, I2CDmaFlags, I2CError, I2CHangDiagnosis, I2CMaster, I2CSlave,
+    I2CSlaveReq, I2CStatus, I2CTimingReport, I2CTransactionOp,
 };
-use drone_cortexm::{fib, reg::prelude::*, thr::prelude::*};
+#[cfg(feature = "dry-run")]
+use crate::{dry_run, I2COp};
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    marker::PhantomData,
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+use drone_core::sync::spsc::oneshot;
+use drone_cortexm::{drv::timer::Timer, fib, processor, reg::prelude::*, thr::prelude::*};
 use drone_stm32_map::periph::{
     dma::ch::{traits::*, DmaChMap, DmaChPeriph},
+    gpio::pin::{GpioPinMap, GpioPinPeriph},
     i2c::{traits::*, I2CMap, I2CPeriph},
 };
 use futures::prelude::*;
 
+type DefaultTimeoutFn = Box<dyn FnMut() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
 /// I²C setup.
+///
+/// Can be created with a struct literal, or with [`I2CSetup::builder`], which
+/// fills in the defaults for the optional values.
 pub struct I2CSetup<
     I2C: I2CMap,
     I2CEv: IntToken,
@@ -62,6 +89,35 @@ pub struct I2CSetup<
     /// This will be written to I2C_CCR.F/S and I2C_CCR.DUTY fields. See the
     /// reference manual for details.
     pub i2c_mode: I2CMode,
+    /// I²C analog noise filter disable.
+    ///
+    /// The analog filter suppresses spikes shorter than 50 ns on SDA and SCL.
+    /// It's enabled when this is `false`, which is the reset value.
+    ///
+    /// This will be written to I2C_FLTR.ANOFF field. See the reference manual
+    /// for details.
+    pub i2c_anfoff: bool,
+    /// I²C digital noise filter.
+    ///
+    /// The digital filter suppresses spikes shorter than `i2c_dnf` periods of
+    /// the peripheral clock. The value is in range 0 to 15, where 0 disables
+    /// the filter. Note that enabling the digital filter lowers the maximum
+    /// SCL clock, see the reference manual for the constraints.
+    ///
+    /// This will be written to I2C_FLTR.DNF field. See the reference manual
+    /// for details.
+    pub i2c_dnf: u8,
+    /// SMBus mode.
+    ///
+    /// In SMBus mode, the peripheral detects the SMBus clock low timeouts, and
+    /// reports them as [`I2CError::Timeout`]: SCL held low for more than 25
+    /// ms, or the cumulative clock low extension exceeding 10 ms for a master
+    /// or 25 ms for a slave. The timeout durations are fixed by the hardware.
+    /// In I²C mode, the timeouts are not detected.
+    ///
+    /// This will be written to I2C_CR1.SMBUS and I2C_CR1.SMBTYPE fields. See
+    /// the reference manual for details.
+    pub i2c_smbus: I2CSmbusMode,
     /// DMA Tx channel peripheral.
     pub dma_tx: DmaChPeriph<DmaTx>,
     /// DMA Tx channel interrupt.
@@ -76,6 +132,22 @@ pub struct I2CSetup<
     /// This will be written to DMA_SxCR.PL field. See the reference manual for
     /// details.
     pub dma_tx_pl: u32,
+    /// DMA Tx channel memory burst transfer configuration.
+    ///
+    /// This will be written to DMA_SxCR.MBURST field. See the reference manual
+    /// for details.
+    ///
+    /// Zero value selects single transfers in direct mode. Non-zero values
+    /// enable the DMA FIFO with the threshold matching the burst size, so that
+    /// the memory side is accessed in 4, 8, or 16 byte bursts, while the
+    /// peripheral side stays single byte. This reduces AHB bus occupancy, but
+    /// adds the following constraints on the session buffer slices passed to
+    /// write operations:
+    ///
+    /// * The slice length must be a multiple of the burst size.
+    ///
+    /// * The slice must not cross a 1 KB address boundary within a burst.
+    pub dma_tx_mburst: u32,
     /// DMA Rx channel peripheral.
     pub dma_rx: DmaChPeriph<DmaRx>,
     /// DMA Rx channel interrupt.
@@ -90,10 +162,276 @@ pub struct I2CSetup<
     /// This will be written to DMA_SxCR.PL field. See the reference manual for
     /// details.
     pub dma_rx_pl: u32,
+    /// DMA Rx channel memory burst transfer configuration.
+    ///
+    /// This will be written to DMA_SxCR.MBURST field. See the reference manual
+    /// for details.
+    ///
+    /// See [`dma_tx_mburst`](I2CSetup::dma_tx_mburst) for the constraints,
+    /// which apply to read operations here.
+    pub dma_rx_mburst: u32,
+    /// Error hook.
+    ///
+    /// Called from the interrupt handlers with the kind of each DMA error and
+    /// I²C bus error. Bus errors are also returned from the [`I2CMaster`]
+    /// operations, while DMA errors don't abort the operation in progress. A
+    /// transfer error disables the DMA stream, so the operation never
+    /// completes. It should be recovered from a supervisor task, e.g. by
+    /// dropping the operation future after a timeout and calling
+    /// [`I2CDrv::swrst`].
+    ///
+    /// If `None`, DMA errors are handled via panicking.
+    pub on_error: Option<fn(I2CErrorKind)>,
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    /// Creates a new [`I2CSetupBuilder`] from the peripherals.
+    ///
+    /// `i2c` is the [`i2c`](I2CSetup::i2c) peripheral with its
+    /// [`i2c_ev`](I2CSetup::i2c_ev) and [`i2c_er`](I2CSetup::i2c_er)
+    /// interrupts, `dma_tx` and `dma_rx` are the DMA channel peripherals with
+    /// their interrupts. The values are set with the builder methods of the
+    /// [`I2CSetup`] field names, and default as follows:
+    ///
+    /// * [`i2c_freq`](I2CSetup::i2c_freq), [`i2c_presc`](I2CSetup::i2c_presc),
+    ///   and [`i2c_trise`](I2CSetup::i2c_trise) - `0`. These have no sensible
+    ///   default, and must be set, otherwise [`I2CDrv::init`] fails.
+    ///
+    /// * [`dma_tx_ch`](I2CSetup::dma_tx_ch) and
+    ///   [`dma_rx_ch`](I2CSetup::dma_rx_ch) - `0`. These must be set to the
+    ///   channels of the I²C peripheral requests, see the reference manual.
+    ///
+    /// * [`i2c_mode`](I2CSetup::i2c_mode) - [`I2CMode::Fm2`].
+    ///
+    /// * [`i2c_anfoff`](I2CSetup::i2c_anfoff) - `false`, analog noise filter
+    ///   enabled.
+    ///
+    /// * [`i2c_dnf`](I2CSetup::i2c_dnf) - `0`, digital noise filter disabled.
+    ///
+    /// * [`i2c_smbus`](I2CSetup::i2c_smbus) - [`I2CSmbusMode::Disabled`].
+    ///
+    /// * [`dma_tx_pl`](I2CSetup::dma_tx_pl) and
+    ///   [`dma_rx_pl`](I2CSetup::dma_rx_pl) - `0b11`, very high priority.
+    ///
+    /// * [`dma_tx_mburst`](I2CSetup::dma_tx_mburst) and
+    ///   [`dma_rx_mburst`](I2CSetup::dma_rx_mburst) - `0b00`, single
+    ///   transfers.
+    ///
+    /// * [`on_error`](I2CSetup::on_error) - `None`, panic on DMA errors.
+    #[must_use]
+    pub fn builder(
+        i2c: (I2CPeriph<I2C>, I2CEv, I2CEr),
+        dma_tx: (DmaChPeriph<DmaTx>, DmaTxInt),
+        dma_rx: (DmaChPeriph<DmaRx>, DmaRxInt),
+    ) -> I2CSetupBuilder<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        let (i2c, i2c_ev, i2c_er) = i2c;
+        let (dma_tx, dma_tx_int) = dma_tx;
+        let (dma_rx, dma_rx_int) = dma_rx;
+        I2CSetupBuilder(Self {
+            i2c,
+            i2c_ev,
+            i2c_er,
+            i2c_freq: 0,
+            i2c_presc: 0,
+            i2c_trise: 0,
+            i2c_mode: I2CMode::Fm2,
+            i2c_anfoff: false,
+            i2c_dnf: 0,
+            i2c_smbus: I2CSmbusMode::Disabled,
+            dma_tx,
+            dma_tx_int,
+            dma_tx_ch: 0,
+            dma_tx_pl: 0b11,
+            dma_tx_mburst: 0b00,
+            dma_rx,
+            dma_rx_int,
+            dma_rx_ch: 0,
+            dma_rx_pl: 0b11,
+            dma_rx_mburst: 0b00,
+            on_error: None,
+        })
+    }
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> fmt::Debug for I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("I2CSetup")
+            .field("i2c_freq", &self.i2c_freq)
+            .field("i2c_presc", &self.i2c_presc)
+            .field("i2c_trise", &self.i2c_trise)
+            .field("i2c_mode", &self.i2c_mode)
+            .field("i2c_anfoff", &self.i2c_anfoff)
+            .field("i2c_dnf", &self.i2c_dnf)
+            .field("i2c_smbus", &self.i2c_smbus)
+            .field("dma_tx_ch", &self.dma_tx_ch)
+            .field("dma_tx_pl", &self.dma_tx_pl)
+            .field("dma_tx_mburst", &self.dma_tx_mburst)
+            .field("dma_rx_ch", &self.dma_rx_ch)
+            .field("dma_rx_pl", &self.dma_rx_pl)
+            .field("dma_rx_mburst", &self.dma_rx_mburst)
+            .finish()
+    }
+}
+
+/// [`I2CSetup`] builder.
+///
+/// Created by [`I2CSetup::builder`].
+pub struct I2CSetupBuilder<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+>(I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>);
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> I2CSetupBuilder<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    /// Sets [`I2CSetup::i2c_freq`].
+    #[must_use]
+    pub fn i2c_freq(mut self, i2c_freq: u32) -> Self {
+        self.0.i2c_freq = i2c_freq;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_presc`].
+    #[must_use]
+    pub fn i2c_presc(mut self, i2c_presc: u32) -> Self {
+        self.0.i2c_presc = i2c_presc;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_trise`].
+    #[must_use]
+    pub fn i2c_trise(mut self, i2c_trise: u32) -> Self {
+        self.0.i2c_trise = i2c_trise;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_mode`].
+    #[must_use]
+    pub fn i2c_mode(mut self, i2c_mode: I2CMode) -> Self {
+        self.0.i2c_mode = i2c_mode;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_anfoff`].
+    #[must_use]
+    pub fn i2c_anfoff(mut self, i2c_anfoff: bool) -> Self {
+        self.0.i2c_anfoff = i2c_anfoff;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_dnf`].
+    #[must_use]
+    pub fn i2c_dnf(mut self, i2c_dnf: u8) -> Self {
+        self.0.i2c_dnf = i2c_dnf;
+        self
+    }
+
+    /// Sets [`I2CSetup::i2c_smbus`].
+    #[must_use]
+    pub fn i2c_smbus(mut self, i2c_smbus: I2CSmbusMode) -> Self {
+        self.0.i2c_smbus = i2c_smbus;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_tx_ch`].
+    #[must_use]
+    pub fn dma_tx_ch(mut self, dma_tx_ch: u32) -> Self {
+        self.0.dma_tx_ch = dma_tx_ch;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_tx_pl`].
+    #[must_use]
+    pub fn dma_tx_pl(mut self, dma_tx_pl: u32) -> Self {
+        self.0.dma_tx_pl = dma_tx_pl;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_tx_mburst`].
+    #[must_use]
+    pub fn dma_tx_mburst(mut self, dma_tx_mburst: u32) -> Self {
+        self.0.dma_tx_mburst = dma_tx_mburst;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_rx_ch`].
+    #[must_use]
+    pub fn dma_rx_ch(mut self, dma_rx_ch: u32) -> Self {
+        self.0.dma_rx_ch = dma_rx_ch;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_rx_pl`].
+    #[must_use]
+    pub fn dma_rx_pl(mut self, dma_rx_pl: u32) -> Self {
+        self.0.dma_rx_pl = dma_rx_pl;
+        self
+    }
+
+    /// Sets [`I2CSetup::dma_rx_mburst`].
+    #[must_use]
+    pub fn dma_rx_mburst(mut self, dma_rx_mburst: u32) -> Self {
+        self.0.dma_rx_mburst = dma_rx_mburst;
+        self
+    }
+
+    /// Sets [`I2CSetup::on_error`].
+    #[must_use]
+    pub fn on_error(mut self, on_error: Option<fn(I2CErrorKind)>) -> Self {
+        self.0.on_error = on_error;
+        self
+    }
+
+    /// Returns the [`I2CSetup`] to be passed to [`I2CDrv::init`].
+    #[must_use]
+    pub fn build(self) -> I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        self.0
+    }
+}
+
+/// SMBus mode of the peripheral.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CSmbusMode {
+    /// I²C mode.
+    Disabled,
+    /// SMBus mode with the SMBus Device type.
+    Device,
+    /// SMBus mode with the SMBus Host type.
+    Host,
 }
 
 /// I²C bus mode.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum I2CMode {
     /// Standard-mode with t<sub>low</sub>/t<sub>high</sub> = 1 duty cycle.
     Sm1,
@@ -103,7 +441,200 @@ pub enum I2CMode {
     Fm169,
 }
 
+impl I2CMode {
+    /// Returns the t<sub>low</sub>/t<sub>high</sub> ratio of the SCL clock as
+    /// a `(low, high)` pair.
+    ///
+    /// Given T<sub>PCLK</sub> = 1 ÷ [`I2CSetup::i2c_freq`] μs and `ccr` =
+    /// [`I2CSetup::i2c_presc`], the resulting SCL timing is:
+    ///
+    /// * t<sub>low</sub> = `low` × `ccr` × T<sub>PCLK</sub>
+    ///
+    /// * t<sub>high</sub> = `high` × `ccr` × T<sub>PCLK</sub>
+    ///
+    /// The actual timing is also affected by the SCL rise time, which depends
+    /// on the bus capacitance and the pull-up resistance. For the same SCL
+    /// frequency, [`I2CMode::Fm169`] gives a longer t<sub>high</sub> than
+    /// [`I2CMode::Fm2`], which leaves more room for slow rising edges on
+    /// heavily loaded buses.
+    #[must_use]
+    pub const fn duty_ratio(self) -> (u32, u32) {
+        match self {
+            Self::Sm1 => (1, 1),
+            Self::Fm2 => (2, 1),
+            Self::Fm169 => (16, 9),
+        }
+    }
+
+    /// Returns the minimum SCL frequency in Hz achievable in this mode with
+    /// the peripheral clock frequency `pclk_hz`, which is bounded by the
+    /// maximum value of the 12-bit CCR.CCR field.
+    fn min_scl_frequency(self, pclk_hz: u32) -> u32 {
+        let divisor = CCR_MAX * self.period_units();
+        (pclk_hz + divisor - 1) / divisor
+    }
+
+    /// Returns the maximum SCL frequency in Hz achievable in this mode with
+    /// the peripheral clock frequency `pclk_hz`, which is bounded by the
+    /// minimum allowed value of the CCR.CCR field, and by the mode limit.
+    fn max_scl_frequency(self, pclk_hz: u32) -> u32 {
+        let (ccr_min, limit) = match self {
+            Self::Sm1 => (4, 100_000),
+            Self::Fm2 | Self::Fm169 => (1, 400_000),
+        };
+        (pclk_hz / (ccr_min * self.period_units())).min(limit)
+    }
+
+    /// Calculates the [`I2CSetup::i2c_presc`] value for the SCL frequency
+    /// `scl_hz` in this mode, with the peripheral clock frequency `pclk_hz`,
+    /// both in Hz.
+    ///
+    /// This is the SCL clock formula of [`I2CSetup::i2c_presc`] solved for the
+    /// prescaler. The result is rounded up, so that the resulting SCL
+    /// frequency doesn't exceed `scl_hz`. It's not clamped to the legal range
+    /// of the CCR.CCR field, so that an impossible speed target can be caught
+    /// by a `const` check of the result, or by [`I2CConfig::validate`].
+    ///
+    /// Being a `const fn`, it can compute the setup constants at compile
+    /// time.
+    ///
+    /// # Panics
+    ///
+    /// If `scl_hz` is zero, with a division by zero. In a `const` item this is
+    /// a compile-time error.
+    #[must_use]
+    pub const fn ccr(self, pclk_hz: u32, scl_hz: u32) -> u32 {
+        let divisor = scl_hz * self.period_units();
+        (pclk_hz + divisor - 1) / divisor
+    }
+
+    /// Calculates the [`I2CSetup::i2c_trise`] value for the maximum rise time
+    /// `max_rise_ns` in nanoseconds, with the peripheral clock frequency
+    /// `pclk_hz` in Hz.
+    ///
+    /// This is the formula of [`I2CSetup::i2c_trise`]. Being a `const fn`, it
+    /// can compute the setup constants at compile time.
+    #[must_use]
+    pub const fn trise(pclk_hz: u32, max_rise_ns: u32) -> u32 {
+        (pclk_hz as u64 * max_rise_ns as u64 / 1_000_000_000) as u32 + 1
+    }
+
+    /// Derives the bus mode, the [`I2CSetup::i2c_presc`] value, and the
+    /// [`I2CSetup::i2c_trise`] value for the SCL frequency `scl_hz` in Hz.
+    /// `pclk_mhz` is the peripheral clock frequency, the same value as
+    /// [`I2CSetup::i2c_freq`].
+    ///
+    /// [`I2CMode::Sm1`] is chosen for frequencies up to 100 kHz, and
+    /// [`I2CMode::Fm2`] above. The prescaler is rounded up, so that the
+    /// resulting SCL frequency doesn't exceed `scl_hz`, and is clamped to the
+    /// legal range of the CCR.CCR field. The rise time value is calculated
+    /// from the maximum rise time of the chosen mode, as described in
+    /// [`I2CSetup::i2c_trise`].
+    ///
+    /// The result is returned as a `(mode, presc, trise)` tuple.
+    ///
+    /// # Panics
+    ///
+    /// If `scl_hz` is zero or exceeds 400 kHz.
+    #[must_use]
+    pub fn timings(pclk_mhz: u32, scl_hz: u32) -> (Self, u32, u32) {
+        assert!(scl_hz > 0 && scl_hz <= 400_000, "SCL frequency out of range");
+        let (mode, ccr_min, t_r_ns) =
+            if scl_hz <= 100_000 { (Self::Sm1, 4, 1000) } else { (Self::Fm2, 1, 300) };
+        let presc = mode.ccr(pclk_mhz * 1_000_000, scl_hz).max(ccr_min).min(CCR_MAX);
+        let trise = Self::trise(pclk_mhz * 1_000_000, t_r_ns);
+        (mode, presc, trise)
+    }
+
+    const fn period_units(self) -> u32 {
+        let (low, high) = self.duty_ratio();
+        low + high
+    }
+}
+
+/// I²C driver configuration.
+///
+/// A copy of the settings passed to [`I2CDrv::init`], which are not tied to a
+/// particular peripheral. Can be obtained with [`I2CDrv::config`] to set up
+/// another bus identically.
+#[derive(Clone, Copy, Debug)]
+pub struct I2CConfig {
+    /// See [`I2CSetup::i2c_freq`].
+    pub i2c_freq: u32,
+    /// See [`I2CSetup::i2c_presc`].
+    pub i2c_presc: u32,
+    /// See [`I2CSetup::i2c_trise`].
+    pub i2c_trise: u32,
+    /// See [`I2CSetup::i2c_mode`].
+    pub i2c_mode: I2CMode,
+    /// See [`I2CSetup::i2c_anfoff`].
+    pub i2c_anfoff: bool,
+    /// See [`I2CSetup::i2c_dnf`].
+    pub i2c_dnf: u8,
+    /// See [`I2CSetup::i2c_smbus`].
+    pub i2c_smbus: I2CSmbusMode,
+    /// See [`I2CSetup::dma_tx_pl`].
+    pub dma_tx_pl: u32,
+    /// See [`I2CSetup::dma_tx_mburst`].
+    pub dma_tx_mburst: u32,
+    /// See [`I2CSetup::dma_rx_pl`].
+    pub dma_rx_pl: u32,
+    /// See [`I2CSetup::dma_rx_mburst`].
+    pub dma_rx_mburst: u32,
+}
+
+impl I2CConfig {
+    /// Checks the I²C values against the constraints of the reference manual.
+    ///
+    /// # Errors
+    ///
+    /// If a value is out of range, see [`I2CConfigError`].
+    pub fn validate(&self) -> Result<(), I2CConfigError> {
+        let (freq_min, presc_min) = match self.i2c_mode {
+            I2CMode::Sm1 => (2, 4),
+            I2CMode::Fm2 | I2CMode::Fm169 => (4, 1),
+        };
+        if self.i2c_freq < freq_min || self.i2c_freq > FREQ_MAX {
+            Err(I2CConfigError::Freq)
+        } else if self.i2c_presc < presc_min || self.i2c_presc > CCR_MAX {
+            Err(I2CConfigError::Presc)
+        } else if self.i2c_trise == 0 || self.i2c_trise > self.i2c_freq + 1 {
+            Err(I2CConfigError::Trise)
+        } else if self.i2c_dnf > 0xF {
+            Err(I2CConfigError::Dnf)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// I²C driver.
+///
+/// # Interrupts
+///
+/// A DMA transfer wakes up the interrupt handlers the following number of
+/// times, not counting errors:
+///
+/// * write: 3 event interrupts (Start, address, and byte transfer finished);
+/// * read: 2 event interrupts (Start and address), and 1 DMA transfer complete
+///   interrupt;
+/// * each additional DMA run of a transfer exceeding the DMA size limit: 1 DMA
+///   transfer complete interrupt, and for writes 1 event interrupt.
+///
+/// The counts follow from the handler flow, and are not measured on the
+/// target. The data phase of reads completes on the DMA transfer complete
+/// interrupt alone, with the event interrupt disabled. The data phase of
+/// writes can't do the same: the DMA transfer completes when the last byte is
+/// written to DR, before it's shifted out, and the Stop or repeated Start
+/// signal must wait for the byte transfer finished flag. The Start and
+/// address events are cleared only by the software sequences of the reference
+/// manual, so they take an interrupt each as well.
+///
+/// The event interrupt is enabled only after the Start bit is set, so that a
+/// byte transfer finished flag left from the previous write doesn't cause an
+/// extra wakeup before a repeated Start. Empty transfers and transfers shorter
+/// than [`I2CDrv::set_int_threshold`] don't use DMA and take an event
+/// interrupt per byte instead.
 pub struct I2CDrv<
     I2C: I2CMap,
     I2CEv: IntToken,
@@ -120,6 +651,23 @@ pub struct I2CDrv<
     dma_tx_int: DmaTxInt,
     dma_rx: DmaChDiverged<DmaRx>,
     dma_rx_int: DmaRxInt,
+    config: I2CConfig,
+    watchdog: fn(),
+    int_threshold: usize,
+    arb_retries: u8,
+    last_pec: u8,
+    nack_counts: NackCounts,
+    timeout: Option<DefaultTimeoutFn>,
+    err: Arc<ErrorSlot>,
+    on_error: Option<fn(I2CErrorKind)>,
+    smbus_alert: Arc<AtomicBool>,
+    detached: Arc<AtomicBool>,
+    #[cfg(feature = "ack-timing")]
+    ack_clock: Option<fn() -> u32>,
+    #[cfg(feature = "ack-timing")]
+    ack_timing: Arc<AckTiming>,
+    #[cfg(feature = "dry-run")]
+    dry_run: Option<Vec<I2COp>>,
 }
 
 impl<
@@ -132,9 +680,19 @@ impl<
     DmaRxInt: IntToken,
 > I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
 {
-    /// Sets up a new [`I2CDrv`] from `setup` values.
-    #[must_use]
-    pub fn init(setup: I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>) -> Self {
+    /// Sets up a new [`I2CDrv`] from `setup` values, checking the I²C values
+    /// first.
+    ///
+    /// The values are checked with [`I2CConfig::validate`]. An out-of-range
+    /// value would otherwise silently produce a broken bus.
+    ///
+    /// # Errors
+    ///
+    /// If the I²C values are out of range. The peripherals are not touched,
+    /// and are dropped with `setup`.
+    pub fn init(
+        setup: I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+    ) -> Result<Self, I2CConfigError> {
         let I2CSetup {
             i2c,
             i2c_ev,
@@ -143,15 +701,35 @@ impl<
             i2c_presc,
             i2c_trise,
             i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
             dma_tx,
             dma_tx_int,
             dma_tx_ch,
             dma_tx_pl,
+            dma_tx_mburst,
             dma_rx,
             dma_rx_int,
             dma_rx_ch,
             dma_rx_pl,
+            dma_rx_mburst,
+            on_error,
         } = setup;
+        let config = I2CConfig {
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            dma_tx_pl,
+            dma_tx_mburst,
+            dma_rx_pl,
+            dma_rx_mburst,
+        };
+        config.validate()?;
         let mut drv = Self {
             i2c: i2c.into(),
             i2c_ev,
@@ -160,177 +738,2372 @@ impl<
             dma_tx_int,
             dma_rx: dma_rx.into(),
             dma_rx_int,
+            config,
+            watchdog: || {},
+            int_threshold: 0,
+            arb_retries: 0,
+            last_pec: 0,
+            nack_counts: NackCounts::new(),
+            timeout: None,
+            err: ErrorSlot::new(),
+            on_error,
+            smbus_alert: Arc::new(AtomicBool::new(false)),
+            detached: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "ack-timing")]
+            ack_clock: None,
+            #[cfg(feature = "ack-timing")]
+            ack_timing: AckTiming::new(),
+            #[cfg(feature = "dry-run")]
+            dry_run: None,
         };
-        drv.init_i2c(i2c_freq, i2c_presc, i2c_trise, i2c_mode);
-        drv.init_dma_tx(dma_tx_ch, dma_tx_pl);
-        drv.init_dma_rx(dma_rx_ch, dma_rx_pl);
-        drv
+        init_i2c(
+            &mut drv.i2c,
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            true,
+        );
+        drv.init_i2c_err();
+        drv.init_dma_tx(dma_tx_ch, dma_tx_pl, dma_tx_mburst);
+        drv.init_dma_rx(dma_rx_ch, dma_rx_pl, dma_rx_mburst);
+        Ok(drv)
+    }
+
+    /// Tears down the driver and returns the setup values it was initialized
+    /// with, including the owned peripherals and interrupt tokens.
+    ///
+    /// This method blocks until the previous Stop signal generation is
+    /// finished, calling the watchdog hook. Then it disables the DMA streams,
+    /// the I²C interrupts, and the peripheral, and clears the peripheral clock
+    /// enable bit.
+    ///
+    /// [`I2CDrv::init`] registers two long-lived fibers: the I²C error fiber
+    /// on the `i2c_er` thread, and the DMA Tx transfer error fiber on the
+    /// `dma_tx_int` thread. Both are detached by waking them up for the last
+    /// time. No long-lived fibers are registered on the `i2c_ev` and
+    /// `dma_rx_int` threads. The fibers on those threads, as well as the DMA
+    /// Tx chunk reloading fibers, belong to single operations and complete
+    /// with them.
+    ///
+    /// The returned values can be passed to [`I2CDrv::init`] again, or the
+    /// peripherals can be reused for another purpose, e.g. the SCL and SDA
+    /// pins can be reconfigured. The only fibers surviving `free` are those of
+    /// operation futures dropped before completion, which can stay attached
+    /// to the interrupt threads until the next interrupt.
+    #[must_use]
+    pub fn free(self) -> I2CSetup<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        let dma_tx_ch = self.dma_tx.dma_ccr.chsel().read_bits();
+        let dma_rx_ch = self.dma_rx.dma_ccr.chsel().read_bits();
+        self.dma_tx.dma_ccr.modify_reg(|r, v| {
+            r.en().clear(v); // stream disable
+            r.tcie().clear(v); // transfer complete interrupt disable
+            r.teie().clear(v); // transfer error interrupt disable
+        });
+        self.dma_rx.dma_ccr.modify_reg(|r, v| {
+            r.en().clear(v); // stream disable
+            r.tcie().clear(v); // transfer complete interrupt disable
+            r.teie().clear(v); // transfer error interrupt disable
+        });
+        self.i2c.i2c_cr2.modify_reg(|r, v| {
+            r.itevten().clear(v); // event interrupt disable
+            r.iterren().clear(v); // error interrupt disable
+            r.dmaen().clear(v); // DMA requests disable
+        });
+        self.i2c.i2c_cr1.store_reg(|r, v| r.pe().clear(v)); // peripheral disable
+        // complete the long-lived fibers registered by `init`
+        self.detached.store(true, Ordering::Release);
+        unsafe { I2CEr::wakeup_unchecked() };
+        unsafe { DmaTxInt::wakeup_unchecked() };
+        self.i2c.rcc_busenr_i2cen.clear_bit(); // I2C clock disable
+        let Self {
+            i2c,
+            i2c_ev,
+            i2c_er,
+            dma_tx,
+            dma_tx_int,
+            dma_rx,
+            dma_rx_int,
+            config,
+            on_error,
+            ..
+        } = self;
+        let I2CConfig {
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            dma_tx_pl,
+            dma_tx_mburst,
+            dma_rx_pl,
+            dma_rx_mburst,
+        } = config;
+        I2CSetup {
+            i2c: i2c.into(),
+            i2c_ev,
+            i2c_er,
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            dma_tx: dma_tx.into(),
+            dma_tx_int,
+            dma_tx_ch,
+            dma_tx_pl,
+            dma_tx_mburst,
+            dma_rx: dma_rx.into(),
+            dma_rx_int,
+            dma_rx_ch,
+            dma_rx_pl,
+            dma_rx_mburst,
+            on_error,
+        }
     }
 
     /// Creates a new master session.
     ///
     /// This method can block if previous Stop signal generation is not
-    /// finished.
+    /// finished. The watchdog hook is called while blocking.
     ///
     /// The returned session object takes ownership of `buf`, which can be later
-    /// returned by [`I2CMaster::stop`] method. If the `stop` method is not
-    /// called, `buf` is leaked.
+    /// returned by [`I2CMaster::stop`] method. See [`I2CMaster`] for what
+    /// happens to `buf` if the `stop` method is not called.
     #[inline]
     pub fn master(
         &mut self,
         buf: Box<[u8]>,
     ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
-        while self.i2c.i2c_cr1.stop().read_bit() {} // stop generation
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
         I2CMaster::new(self, buf)
     }
 
-    pub(crate) unsafe fn write(&mut self, addr: u8, buf_tx: &[u8]) -> impl Future<Output = ()> {
-        self.dma_tx(buf_tx);
-        self.start(addr << 1, false)
-    }
-
-    pub(crate) unsafe fn read(&mut self, addr: u8, buf_rx: &mut [u8]) -> impl Future<Output = ()> {
-        let dma_rx = self.dma_rx(buf_rx);
-        self.start(addr << 1 | 1, buf_rx.len() > 1).then(|()| dma_rx)
+    /// Creates a new master session, waiting for the previous Stop signal
+    /// generation asynchronously.
+    ///
+    /// Unlike [`I2CDrv::master`], this method doesn't block. While the Stop
+    /// signal generation is not finished, the returned future yields to the
+    /// executor between checks, letting the other fibers of the thread run.
+    /// The Stop generation end doesn't raise an interrupt, so the future wakes
+    /// itself up immediately, and the thread stays busy until then.
+    pub async fn master_async(
+        &mut self,
+        buf: Box<[u8]>,
+    ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        future::poll_fn(|cx| {
+            if i2c_cr1.stop().read_bit() {
+                // stop generation
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+        I2CMaster::new(self, buf)
     }
 
-    pub(crate) fn stop(&mut self) {
-        self.i2c.i2c_cr1.stop().set_bit(); // stop generation
+    /// Creates a new master session if the previous Stop signal generation is
+    /// finished.
+    ///
+    /// This is a non-blocking variant of [`I2CDrv::master`].
+    ///
+    /// # Errors
+    ///
+    /// If the previous Stop signal generation is not finished, `buf` is
+    /// returned back.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_master(
+        &mut self,
+        buf: Box<[u8]>,
+    ) -> Result<I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>, Box<[u8]>> {
+        if self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            return Err(buf);
+        }
+        Ok(I2CMaster::new(self, buf))
     }
 
-    unsafe fn dma_tx(&mut self, buf_tx: &[u8]) {
-        self.dma_tx.dma_cm0ar.store_reg(|r, v| {
+    /// Creates a new master session with a borrowed buffer.
+    ///
+    /// This is the same as [`I2CDrv::master`], but the session borrows `buf`
+    /// instead of taking ownership of a [`Box`]. The [`I2CMaster::stop`]
+    /// method returns the borrow back.
+    ///
+    /// This method can block if previous Stop signal generation is not
+    /// finished. The watchdog hook is called while blocking.
+    ///
+    /// # Safety
+    ///
+    /// The session must not be leaked, e.g. with [`core::mem::forget`], while
+    /// an operation is in progress, i.e. after its future is leaked as well.
+    /// A boxed buffer is leaked in this case, but a borrowed one can't be, so
+    /// the DMA or the event interrupt handler could access `buf` after the
+    /// borrow ends. Dropping the session or the futures is fine, see
+    /// [`I2CMaster`].
+    #[inline]
+    pub unsafe fn master_borrowed<'b>(
+        &mut self,
+        buf: &'b mut [u8],
+    ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, &'b mut [u8]> {
+        self.master_unchecked(buf)
+    }
+
+    /// Creates a new master session with a buffer of any [`DmaSafe`]
+    /// container type, e.g. a [`Vec`](alloc::vec::Vec) or a `&'static mut [u8]` DMA buffer.
+    ///
+    /// This is the same as [`I2CDrv::master`], but the session takes
+    /// ownership of `buf` of the `B` type, and the [`I2CMaster::stop`] method
+    /// returns the original container.
+    ///
+    /// This method can block if previous Stop signal generation is not
+    /// finished. The watchdog hook is called while blocking.
+    #[inline]
+    pub fn master_with<B: DmaSafe>(
+        &mut self,
+        buf: B,
+    ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, B> {
+        unsafe { self.master_unchecked(buf) }
+    }
+
+    /// Creates a new master session with a buffer of any container type.
+    ///
+    /// # Safety
+    ///
+    /// The session must not be leaked while an operation is in progress,
+    /// unless `buf` satisfies the [`DmaSafe`] contract.
+    unsafe fn master_unchecked<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &mut self,
+        buf: B,
+    ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, B> {
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        I2CMaster::new(self, buf)
+    }
+
+    /// Creates a new master session without a buffer.
+    ///
+    /// This is useful when the buffer is obtained only after the bus is
+    /// acquired. The buffer is attached later with [`I2CMaster::set_buf`].
+    /// Until then, the session operations using the session buffer panic,
+    /// while the ones staging their data in temporary buffers, such as
+    /// [`I2CMaster::write_regs`], are available.
+    ///
+    /// This method can block if previous Stop signal generation is not
+    /// finished. The watchdog hook is called while blocking.
+    #[inline]
+    pub fn master_empty(
+        &mut self,
+    ) -> I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        I2CMaster::new_empty(self)
+    }
+
+    /// Creates a new slave session, which responds to the 7-bit address `addr`,
+    /// and to the 7-bit `dual` address if it's not `None`.
+    ///
+    /// The addresses are programmed into the OAR1 and OAR2 registers, and the
+    /// acknowledge is enabled. The returned session object takes ownership of
+    /// `buf`, which can be later returned by [`I2CSlave::stop`] method.
+    pub fn slave(
+        &mut self,
+        addr: u8,
+        dual: Option<u8>,
+        buf: Box<[u8]>,
+    ) -> I2CSlave<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
+        // bit 14 of OAR1 should always be kept at 1 by software
+        self.i2c.i2c_oar1.store_bits(1 << 14 | u32::from(addr) << 1); // 7-bit slave address
+        self.i2c.i2c_oar2.store_reg(|r, v| {
+            if let Some(dual) = dual {
+                r.add2().write(v, u32::from(dual)); // interface address
+                r.endual().set(v); // dual addressing mode enable
+            }
+        });
+        self.i2c.i2c_cr1.modify_reg(|r, v| {
+            r.pos().clear(v); // ACK bit controls the current byte
+            r.ack().set(v); // acknowledge enable
+        });
+        I2CSlave::new(self, buf)
+    }
+
+    /// Sends the general call software reset command.
+    ///
+    /// The general call address `0x00` addresses every device on the bus that
+    /// supports it. The meaning of the general call is defined by the
+    /// second byte:
+    ///
+    /// * `0x06` - reset and write the programmable part of the slave address.
+    ///   Devices respond to it as to a power-on reset. This is the command sent
+    ///   by this method.
+    ///
+    /// * `0x04` - write the programmable part of the slave address without
+    ///   resetting.
+    ///
+    /// * `0x00` - not allowed as the second byte.
+    ///
+    /// Note that the command is not acknowledged if none of the devices on the
+    /// bus support the general call, which results in an acknowledge failure.
+    ///
+    /// # Errors
+    ///
+    /// If the command is not acknowledged, or another bus error occurs.
+    pub async fn general_call_reset(&mut self) -> Result<(), I2CError> {
+        let mut master = self.master(vec![0x06].into_boxed_slice());
+        master.write(0x00, ..).await?;
+        drop(master.stop());
+        Ok(())
+    }
+
+    /// Scans the bus for responding devices, and returns a bitmap of the
+    /// addresses, which acknowledged.
+    ///
+    /// An address-only write is sent to each address in range 0x08 to 0x77,
+    /// leaving out the reserved addresses. Bit `n` of the returned value is set
+    /// if the address `n` is acknowledged. An i2cdetect-style grid can be
+    /// printed by testing `map >> addr & 1` for each address.
+    ///
+    /// Note that some devices can misbehave on an address-only write, e.g.
+    /// devices treating it as the SMBus Quick Command. Use the scan only for
+    /// bring-up and diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error other than the acknowledge failure occurs. The scan is
+    /// aborted.
+    pub async fn scan(&mut self) -> Result<u128, I2CError> {
+        let mut map = 0;
+        for addr in 0x08..=0x77 {
+            match self.master_empty().quick(addr, false).await {
+                Ok(_) => map |= 1 << addr,
+                Err(I2CError::Nack(_) | I2CError::DeviceGone) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Polls the 8-bit register `reg` of the slave at the address `addr` until
+    /// its value masked with `mask` equals `value`.
+    ///
+    /// The register is read once immediately, and then on each pulse of
+    /// `timer` with the `interval` period, until `timeout` timer ticks elapse.
+    /// Missed pulses are accounted, so the polling period stays accurate even
+    /// if a read takes longer than `interval`. Returns `true` if the
+    /// condition is met, or `false` on timeout.
+    ///
+    /// This covers the common idiom of waiting for a device BUSY bit to clear,
+    /// e.g. during an EEPROM write cycle.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs on a read. The polling is aborted.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn poll_reg_until<T: Timer>(
+        &mut self,
+        timer: &mut T,
+        addr: u8,
+        reg: u8,
+        mask: u8,
+        value: u8,
+        interval: u32,
+        timeout: u32,
+    ) -> Result<bool, I2CError> {
+        let mut buf = vec![reg, 0].into_boxed_slice();
+        let mut pulses = timer.interval_skip(interval);
+        let mut elapsed = 0_u32;
+        loop {
+            let mut master = self.master(buf);
+            master.write(addr, ..1).await?;
+            master.read(addr, 1..).await?;
+            buf = master.stop();
+            if buf[1] & mask == value {
+                break Ok(true);
+            }
+            if elapsed >= timeout {
+                break Ok(false);
+            }
+            match pulses.next().await {
+                Some(count) => {
+                    elapsed = elapsed.saturating_add((count.get() as u32).saturating_mul(interval));
+                }
+                None => break Ok(false),
+            }
+        }
+    }
+
+    /// Reads the 8-bit register `reg` of the slave at the address `addr`,
+    /// applies `f` to its value, and writes the result back.
+    ///
+    /// The read and the write are performed within a single session chained
+    /// with repeated Start signals, so the bus is held for the whole update.
+    /// On a multi-master bus this prevents other masters from accessing the
+    /// device between the read and the write.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. If it occurs before the write, `f` is not
+    /// applied.
+    pub async fn rmw_reg<F: FnOnce(u8) -> u8>(
+        &mut self,
+        addr: u8,
+        reg: u8,
+        f: F,
+    ) -> Result<(), I2CError> {
+        let mut master = self.master(vec![reg, 0].into_boxed_slice());
+        master.write(addr, ..1).await?;
+        master.read(addr, 1..).await?;
+        master.buf_mut()[1] = f(master.buf()[1]);
+        master.write(addr, ..).await?;
+        drop(master.stop());
+        Ok(())
+    }
+
+    /// Performs a combined transaction of `ops` with the slave at the address
+    /// `addr` in a session with `buf`, and sends the buffer back with the
+    /// result on `tx`.
+    ///
+    /// This is for applications, which centralize the bus access in a
+    /// dedicated task, while the results are consumed by other tasks. The
+    /// operation is the same as [`I2CMaster::transaction`] followed by
+    /// [`I2CMaster::stop`]. The buffer is owned by the session until the
+    /// transaction is finished, so the consumer gets it only after the DMA is
+    /// done with it. If the returned future is dropped before completion, the
+    /// transfer is aborted, and `tx` is dropped, so the receiver is canceled.
+    /// The channel is executor-agnostic, so the receiver can be awaited from
+    /// any thread. If the receiver is already dropped, the buffer is freed.
+    ///
+    /// # Panics
+    ///
+    /// If `ops` is empty, or if a range is out of `buf` bounds.
+    #[allow(clippy::type_complexity)]
+    pub async fn transaction_notify<A: Into<I2CAddr>>(
+        &mut self,
+        addr: A,
+        buf: Box<[u8]>,
+        ops: &[I2CTransactionOp],
+        tx: oneshot::Sender<(Box<[u8]>, Result<(), I2CError>)>,
+    ) {
+        let mut master = self.master(buf);
+        let result = master.transaction(addr, ops).await;
+        drop(tx.send((master.stop(), result)));
+    }
+
+    /// Reads the consecutive registers of the slave at the address `addr`,
+    /// starting from the 8-bit register `reg`, into `buf`.
+    ///
+    /// The register address write and the read are chained with a repeated
+    /// Start signal, and followed by the Stop signal. The transfer is staged
+    /// in a temporary session buffer. This is the same as a session doing
+    /// [`I2CMaster::write_read`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs.
+    pub async fn read_reg(&mut self, addr: u8, reg: u8, buf: &mut [u8]) -> Result<(), I2CError> {
+        self.read_reg_bytes(addr, &[reg], buf).await
+    }
+
+    /// Reads the consecutive registers of the slave at the address `addr`,
+    /// starting from the 8-bit register `reg`, into `buf`, unless `deadline`
+    /// resolves first.
+    ///
+    /// This is for control loops sampling a sensor at a fixed rate, which need
+    /// a bounded latency more than every sample. `deadline` is usually a sleep
+    /// future of a timer set to the loop period budget. Returns `Ok(true)` if
+    /// the read is completed in time, and `buf` holds the fresh data. If the
+    /// deadline is missed, the transfer is aborted, the bus is released with
+    /// the Stop signal, and `Ok(false)` is returned. In this case `buf` is
+    /// left intact, so it keeps the previous, stale sample.
+    ///
+    /// The determinism guarantees are:
+    ///
+    /// * The method returns right after `deadline` resolves: the abort stops
+    ///   the DMA streams, disables the interrupts, and requests the Stop
+    ///   signal, waiting only for the DMA Rx stream to stop.
+    /// * `buf` is either fully updated or not touched at all, because the
+    ///   transfer is staged in a temporary buffer, which is copied only after
+    ///   a successful read.
+    /// * The wait for the bus, e.g. held by another master, counts towards the
+    ///   deadline. The blocking wait for the previous Stop signal generation,
+    ///   see [`I2CDrv::master`], and the allocation of the temporary buffer
+    ///   don't, as they precede the transfer.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs before the deadline. `buf` is left intact.
+    pub async fn read_reg_deadline<T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        reg: u8,
+        buf: &mut [u8],
+        deadline: T,
+    ) -> Result<bool, I2CError> {
+        let mut staged = vec![0; 1 + buf.len()].into_boxed_slice();
+        staged[0] = reg;
+        let mut master = self.master(staged);
+        let result = {
+            let transfer = master.write_read(addr, ..1, 1..);
+            futures::pin_mut!(transfer);
+            futures::pin_mut!(deadline);
+            match future::select(transfer, deadline).await {
+                future::Either::Left((result, _)) => result.map(|()| true),
+                // dropping the transfer future aborts the transfer
+                future::Either::Right(((), _)) => Ok(false),
+            }
+        };
+        let staged = master.stop();
+        if let Ok(true) = result {
+            buf.copy_from_slice(&staged[1..]);
+        }
+        result
+    }
+
+    /// Reads the consecutive registers of the slave at the address `addr`,
+    /// starting from the 16-bit register `reg`, into `buf`.
+    ///
+    /// The register address is sent in big-endian byte order. Otherwise it's
+    /// the same as [`I2CDrv::read_reg`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs.
+    pub async fn read_reg16(&mut self, addr: u8, reg: u16, buf: &mut [u8]) -> Result<(), I2CError> {
+        self.read_reg_bytes(addr, &reg.to_be_bytes(), buf).await
+    }
+
+    /// Writes `data` to the consecutive registers of the slave at the address
+    /// `addr`, starting from the 8-bit register `reg`.
+    ///
+    /// The register address and the data are sent in a single write transfer,
+    /// followed by the Stop signal. The transfer is staged in a temporary
+    /// session buffer.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs.
+    pub async fn write_reg(&mut self, addr: u8, reg: u8, data: &[u8]) -> Result<(), I2CError> {
+        self.write_reg_bytes(addr, &[reg], data).await
+    }
+
+    /// Writes `data` to the consecutive registers of the slave at the address
+    /// `addr`, starting from the 16-bit register `reg`.
+    ///
+    /// The register address is sent in big-endian byte order. Otherwise it's
+    /// the same as [`I2CDrv::write_reg`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs.
+    pub async fn write_reg16(&mut self, addr: u8, reg: u16, data: &[u8]) -> Result<(), I2CError> {
+        self.write_reg_bytes(addr, &reg.to_be_bytes(), data).await
+    }
+
+    /// Reads a single byte from the 8-bit register `reg` of the slave at the
+    /// address `addr`, driving the whole transaction from the I²C event
+    /// interrupt.
+    ///
+    /// This is for control loops reading a single register at a high rate. The
+    /// register address write, the repeated Start, the one-byte read, and the
+    /// Stop signal are all driven by the I²C event interrupt handler as a
+    /// single state machine. Unlike a session doing a write and a read, it
+    /// doesn't program the DMA streams, doesn't allocate a buffer, and wakes up
+    /// the calling task only once, when the value is received. The cost is six
+    /// event interrupts per read. Nothing is cached between the calls: each
+    /// call sets up the transaction from scratch, which is cheap without DMA.
+    ///
+    /// This method can block if previous Stop signal generation is not
+    /// finished. The watchdog hook is called while blocking. Then it waits
+    /// until no other master holds the bus, like the first operation of an
+    /// [`I2CMaster`] session. The wait and the transfer are bounded by the
+    /// timeout of [`I2CDrv::set_timeout`]. In the dry-run mode, the operations
+    /// are recorded and zero is returned.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs.
+    pub async fn read_reg_byte(&mut self, addr: u8, reg: u8) -> Result<u8, I2CError> {
+        #[cfg(feature = "dry-run")]
+        if self.dry_run(I2COp::Write { addr, data: vec![reg] }) {
+            self.dry_run(I2COp::Read { addr, len: 1 });
+            self.dry_run(I2COp::Stop);
+            return Ok(0);
+        }
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        self.clear_error();
+        let timeout = self.default_timeout();
+        let idle = self.bus_idle();
+        if let Some(timeout) = timeout {
+            if let future::Either::Right(((), _)) = future::select(idle, timeout).await {
+                return Err(I2CError::Timeout);
+            }
+        } else {
+            idle.await;
+        }
+        self.i2c.i2c_cr2.dmaen().clear_bit(); // DMA requests disable
+        let guard = self.cancel_guard();
+        let transfer = read_reg_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, reg);
+        let byte = timed(self.default_timeout(), || guard.abort(I2CError::Timeout), transfer).await;
+        guard.disarm();
+        let result = self.take_error().map_or(Ok(()), Err);
+        self.track_nacks(addr, result).map(|()| byte)
+    }
+
+    /// Returns a stream of reads of `len` bytes from the consecutive registers
+    /// of the slave at the address `addr`, starting from the register `reg`.
+    ///
+    /// Each item is polled with a separate session doing the combined
+    /// transaction of [`I2CMaster::write_read`] followed by the Stop signal,
+    /// and yields the received data in a newly allocated buffer. A bus error
+    /// is yielded as an item, and the next item starts a new transaction. The
+    /// stream never ends, and reads the device as fast as it's polled, so it's
+    /// usually paced by a timer.
+    ///
+    /// # Panics
+    ///
+    /// If `len` is zero.
+    pub fn read_reg_stream(
+        &mut self,
+        addr: u8,
+        reg: u8,
+        len: usize,
+    ) -> impl Stream<Item = Result<Box<[u8]>, I2CError>> + '_ {
+        assert!(len > 0, "Empty register read");
+        stream::unfold(self, move |drv| async move {
+            let mut buf = vec![0; 1 + len].into_boxed_slice();
+            buf[0] = reg;
+            let mut master = drv.master(buf);
+            let result = master.write_read(addr, ..1, 1..).await;
+            let buf = master.stop();
+            Some((result.map(|()| buf[1..].into()), drv))
+        })
+    }
+
+    /// Writes `buf_tx` to the slave at the address `addr`, and sends the Stop
+    /// signal, by polling the status flags.
+    ///
+    /// This transfer path doesn't use the interrupts nor the DMA, so it works
+    /// before the interrupt controller is set up, and from a panic handler with
+    /// the interrupts disabled. The I²C interrupts and the DMA requests are
+    /// disabled for the transfer, and restored after it. The calling thread is
+    /// blocked for the whole transfer, and the watchdog hook is called while
+    /// waiting. Use it for bring-up and diagnostics only.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released.
+    pub fn write_blocking(&mut self, addr: u8, buf_tx: &[u8]) -> Result<(), I2CError> {
+        blocking::write_blocking(&self.i2c, self.watchdog, addr, buf_tx)
+    }
+
+    /// Reads from the slave at the address `addr` into `buf_rx`, and sends the
+    /// Stop signal, by polling the status flags.
+    ///
+    /// See [`I2CDrv::write_blocking`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released.
+    ///
+    /// # Panics
+    ///
+    /// If `buf_rx` is empty.
+    pub fn read_blocking(&mut self, addr: u8, buf_rx: &mut [u8]) -> Result<(), I2CError> {
+        blocking::read_blocking(&self.i2c, self.watchdog, addr, buf_rx)
+    }
+
+    /// Sets the watchdog hook, which is called from the busy-wait loops of
+    /// the driver.
+    ///
+    /// The loops are the wait for the previous Stop signal generation in
+    /// [`I2CDrv::master`] and [`I2CDrv::read_reg_byte`], and the wait for the
+    /// reset state in [`I2CDrv::swrst`]. The hook is called on every
+    /// iteration, i.e. every few CPU cycles, so it should be cheap, e.g. a
+    /// single write to the key register of the independent watchdog.
+    /// Operation futures don't block, except for the drop of an unfinished
+    /// operation future, which waits for the DMA to stop accessing the
+    /// buffer, see [`I2CMaster`](crate::I2CMaster).
+    ///
+    /// The default hook does nothing.
+    pub fn set_watchdog_hook(&mut self, hook: fn()) {
+        self.watchdog = hook;
+    }
+
+    /// Returns the own addresses programmed into the peripheral.
+    ///
+    /// The first value is the primary address from the OAR1 register, which is
+    /// either 7-bit or 10-bit depending on OAR1.ADDMODE field. The second value
+    /// is the 7-bit dual address from the OAR2 register, if dual addressing
+    /// mode is enabled.
+    #[must_use]
+    pub fn own_addresses(&self) -> (u16, Option<u16>) {
+        let oar1 = self.i2c.i2c_oar1.load_val();
+        let oar2 = self.i2c.i2c_oar2.load_val();
+        let primary = if self.i2c.i2c_oar1.addmode().read(&oar1) {
+            // 10-bit slave address
+            (self.i2c.i2c_oar1.add10().read(&oar1) << 8
+                | self.i2c.i2c_oar1.add7().read(&oar1) << 1
+                | u32::from(self.i2c.i2c_oar1.add0().read(&oar1))) as u16
+        } else {
+            // 7-bit slave address
+            self.i2c.i2c_oar1.add7().read(&oar1) as u16
+        };
+        let dual = if self.i2c.i2c_oar2.endual().read(&oar2) {
+            Some(self.i2c.i2c_oar2.add2().read(&oar2) as u16)
+        } else {
+            None
+        };
+        (primary, dual)
+    }
+
+    /// Resets the I²C peripheral with CR1.SWRST bit and re-applies the
+    /// configuration passed to [`I2CDrv::init`].
+    ///
+    /// The software reset brings all I²C registers to their reset values and
+    /// releases the SCL and SDA lines on the peripheral side. Use it to recover
+    /// from a peripheral stuck in an inconsistent state, e.g. with SR2.BUSY flag
+    /// set while the bus is idle after a glitch on the lines. The DMA streams,
+    /// the peripheral clock, and the registered interrupt handlers are not
+    /// affected.
+    ///
+    /// The peripheral reset through the RCC bus reset register is a heavier
+    /// alternative, which resets the whole peripheral module including its
+    /// analog filter and clock logic. Try the software reset first, and resort
+    /// to the RCC reset only if the former doesn't help. The configuration can
+    /// be restored after the RCC reset by calling this method.
+    ///
+    /// Note that neither of them can release a bus held by a slave device. If
+    /// a slave keeps SDA low, the bus must be recovered by clocking SCL
+    /// manually with [`I2CDrv::recover_bus`].
+    pub fn swrst(&mut self) {
+        self.i2c.i2c_cr1.store_reg(|r, v| r.swrst().set(v)); // under reset state
+        while !self.i2c.i2c_cr1.swrst().read_bit() {
+            // wait for reset state
+            (self.watchdog)();
+        }
+        self.i2c.i2c_cr1.store_reg(|r, v| r.swrst().clear(v)); // not under reset
+        let I2CConfig {
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            ..
+        } = self.config;
+        setup_i2c(
+            &mut self.i2c,
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            true,
+        );
+    }
+
+    /// Recovers the bus held by a slave device, which keeps SDA low.
+    ///
+    /// A slave interrupted in the middle of a read, e.g. by an MCU reset,
+    /// waits for the rest of the SCL pulses, and keeps SDA low while it sends
+    /// a zero bit. The peripheral can't generate the pulses in this state, so
+    /// this method temporarily switches the `scl` and `sda` pins to the
+    /// general-purpose open-drain output mode, and toggles SCL up to 9 times
+    /// until the slave releases SDA. Then it generates a Stop condition
+    /// manually, switches the pins back to the alternate function mode, and
+    /// resets the peripheral with [`I2CDrv::swrst`].
+    ///
+    /// `half_period` is the number of processor cycles of each SCL half
+    /// period. It should be at least 5 µs worth of cycles to stay within the
+    /// Standard-mode timings. The pins are expected to be configured as in
+    /// the [crate-level example](crate), with the IO port clock enabled.
+    ///
+    /// Returns `true` if SDA is released.
+    pub fn recover_bus<Scl: GpioPinMap, Sda: GpioPinMap>(
+        &mut self,
+        scl: &GpioPinPeriph<Scl>,
+        sda: &GpioPinPeriph<Sda>,
+        half_period: u32,
+    ) -> bool {
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.pe().clear(v)); // peripheral disable
+        scl.gpio_bsrr_bs.set_bit(); // SCL high
+        sda.gpio_bsrr_bs.set_bit(); // SDA high
+        scl.gpio_otyper_ot.set_bit(); // output open-drain
+        sda.gpio_otyper_ot.set_bit(); // output open-drain
+        scl.gpio_moder_moder.write_bits(0b01); // general purpose output
+        sda.gpio_moder_moder.write_bits(0b01); // general purpose output
+        processor::spin(half_period);
+        for _ in 0..9 {
+            if sda.gpio_idr_idr.read_bit() {
+                break;
+            }
+            scl.gpio_bsrr_br.set_bit(); // SCL low
+            processor::spin(half_period);
+            scl.gpio_bsrr_bs.set_bit(); // SCL high
+            while !scl.gpio_idr_idr.read_bit() {
+                // clock stretching
+                (self.watchdog)();
+            }
+            processor::spin(half_period);
+        }
+        // stop generation: SDA rising while SCL is high
+        scl.gpio_bsrr_br.set_bit(); // SCL low
+        processor::spin(half_period);
+        sda.gpio_bsrr_br.set_bit(); // SDA low
+        processor::spin(half_period);
+        scl.gpio_bsrr_bs.set_bit(); // SCL high
+        processor::spin(half_period);
+        sda.gpio_bsrr_bs.set_bit(); // SDA high
+        processor::spin(half_period);
+        let released = sda.gpio_idr_idr.read_bit();
+        scl.gpio_moder_moder.write_bits(0b10); // alternate function
+        sda.gpio_moder_moder.write_bits(0b10); // alternate function
+        self.swrst();
+        released
+    }
+
+    /// Checks that both `scl` and `sda` lines have external pull-up resistors.
+    ///
+    /// Without the pull-ups, or with the bus disconnected, the lines float:
+    /// the Start condition may never complete, or the reads return all ones.
+    /// This method temporarily switches the pins to the input mode with the
+    /// internal pull-down resistors, waits for `settle` processor cycles, and
+    /// samples the lines. An external pull-up overpowers the weak internal
+    /// pull-down, so a line sampled low is reported as missing the pull-up.
+    /// The peripheral is disabled for the time of the check, and the pins are
+    /// switched back to the alternate function mode with their previous
+    /// pull-up/pull-down setting.
+    ///
+    /// Call it at the bring-up, before any transfer, while the bus is idle. A
+    /// slave holding a line low is indistinguishable from a missing pull-up,
+    /// see [`I2CDrv::recover_bus`]. The pins are expected to be configured as
+    /// in the [crate-level example](crate), with the IO port clock enabled.
+    ///
+    /// # Errors
+    ///
+    /// [`I2CConfigError::MissingPullups`] if any line is sampled low.
+    pub fn check_pullups<Scl: GpioPinMap, Sda: GpioPinMap>(
+        &self,
+        scl: &GpioPinPeriph<Scl>,
+        sda: &GpioPinPeriph<Sda>,
+        settle: u32,
+    ) -> Result<(), I2CConfigError> {
+        let pe = self.i2c.i2c_cr1.pe().read_bit();
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.pe().clear(v)); // peripheral disable
+        let scl_pupdr = scl.gpio_pupdr_pupdr.read_bits();
+        let sda_pupdr = sda.gpio_pupdr_pupdr.read_bits();
+        scl.gpio_pupdr_pupdr.write_bits(0b10); // pull-down
+        sda.gpio_pupdr_pupdr.write_bits(0b10); // pull-down
+        scl.gpio_moder_moder.write_bits(0b00); // input
+        sda.gpio_moder_moder.write_bits(0b00); // input
+        processor::spin(settle);
+        let pulled_up = scl.gpio_idr_idr.read_bit() && sda.gpio_idr_idr.read_bit();
+        scl.gpio_moder_moder.write_bits(0b10); // alternate function
+        sda.gpio_moder_moder.write_bits(0b10); // alternate function
+        scl.gpio_pupdr_pupdr.write_bits(scl_pupdr);
+        sda.gpio_pupdr_pupdr.write_bits(sda_pupdr);
+        if pe {
+            self.i2c.i2c_cr1.modify_reg(|r, v| r.pe().set(v)); // peripheral enable
+        }
+        if pulled_up { Ok(()) } else { Err(I2CConfigError::MissingPullups) }
+    }
+
+    /// Checks that the bus is clean before a high-consequence transfer, e.g.
+    /// commanding an actuator.
+    ///
+    /// The check only reads the status registers, and fails with:
+    ///
+    /// * [`I2CError::BusBusy`] if SR2.BUSY is set, i.e. another master holds
+    ///   the bus, or a slave keeps SDA or SCL low. The latter can be cleared
+    ///   with [`I2CDrv::recover_bus`].
+    /// * The error of an error flag set in SR1, e.g. [`I2CError::BusError`],
+    ///   which is not yet handled by the error interrupt handler. This
+    ///   indicates a glitch on the bus, or an error interrupt which doesn't
+    ///   run. The flags are left in place.
+    ///
+    /// See [`I2CDrv::assert_bus_idle_lines`] to check the line levels as well.
+    ///
+    /// # Errors
+    ///
+    /// If any of the conditions above holds.
+    pub fn assert_bus_idle(&self) -> Result<(), I2CError> {
+        if self.i2c.i2c_sr2.busy().read_bit() {
+            return Err(I2CError::BusBusy);
+        }
+        let val = self.i2c.i2c_sr1.load_val();
+        I2CError::load::<I2C>(&val, self.i2c.i2c_sr1).map_or(Ok(()), Err)
+    }
+
+    /// Checks that the bus is clean before a high-consequence transfer, and
+    /// that both `scl` and `sda` lines are high.
+    ///
+    /// This is [`I2CDrv::assert_bus_idle`], followed by sampling the input
+    /// levels of the pins, which are left in the alternate function mode. A
+    /// line sampled low fails with [`I2CError::BusBusy`]. It indicates a slave
+    /// holding the line, which SR2.BUSY can miss if the line was low since the
+    /// peripheral was enabled, or a short circuit. A stuck SDA can be cleared
+    /// with [`I2CDrv::recover_bus`].
+    ///
+    /// # Errors
+    ///
+    /// If any of the conditions above holds.
+    pub fn assert_bus_idle_lines<Scl: GpioPinMap, Sda: GpioPinMap>(
+        &self,
+        scl: &GpioPinPeriph<Scl>,
+        sda: &GpioPinPeriph<Sda>,
+    ) -> Result<(), I2CError> {
+        self.assert_bus_idle()?;
+        if scl.gpio_idr_idr.read_bit() && sda.gpio_idr_idr.read_bit() {
+            Ok(())
+        } else {
+            Err(I2CError::BusBusy)
+        }
+    }
+
+    /// Returns the configuration this driver was set up with.
+    ///
+    /// Peripheral-specific values, such as DMA channel numbers, are not
+    /// included.
+    #[must_use]
+    pub fn config(&self) -> I2CConfig {
+        self.config
+    }
+
+    /// Returns `true` if the peripheral is configured for Fast-mode, by
+    /// reading back the CCR.F/S bit.
+    #[must_use]
+    pub fn is_fast_mode(&self) -> bool {
+        self.i2c.i2c_ccr.f_s().read_bit()
+    }
+
+    /// Returns the bus mode the peripheral is configured for, by reading back
+    /// the CCR.F/S and CCR.DUTY bits.
+    ///
+    /// Unlike [`I2CDrv::config`], this reflects the actual register state.
+    #[must_use]
+    pub fn mode(&self) -> I2CMode {
+        let ccr_val = self.i2c.i2c_ccr.load_val();
+        if !self.i2c.i2c_ccr.f_s().read(&ccr_val) {
+            I2CMode::Sm1
+        } else if self.i2c.i2c_ccr.duty().read(&ccr_val) {
+            I2CMode::Fm169
+        } else {
+            I2CMode::Fm2
+        }
+    }
+
+    /// Computes the SCL timing of the current configuration and checks it
+    /// against the I²C-bus specification.
+    ///
+    /// `pclk_hz` is the peripheral clock frequency in Hz, and `bus_cap_pf` is
+    /// the total capacitive load of the bus lines in pF. See
+    /// [`I2CTimingReport`] for the formulas and the limits.
+    ///
+    /// The data setup and hold times are generated by the peripheral and are
+    /// not reported.
+    #[must_use]
+    pub fn timing_report(&self, pclk_hz: u32, bus_cap_pf: u32) -> I2CTimingReport {
+        I2CTimingReport::new(&self.config, pclk_hz, bus_cap_pf)
+    }
+
+    /// Returns the minimum SCL frequency in Hz achievable with the current
+    /// peripheral clock frequency, see [`I2CSetup::i2c_freq`].
+    ///
+    /// Slow frequencies are driven in Standard-mode, where the frequency is
+    /// bounded by the maximum value of the 12-bit CCR.CCR field. If the target
+    /// frequency is lower, the APB prescaler should be increased.
+    #[must_use]
+    pub fn min_scl_frequency(&self) -> u32 {
+        I2CMode::Sm1.min_scl_frequency(self.pclk_hz())
+    }
+
+    /// Returns the maximum SCL frequency in Hz achievable with the current
+    /// peripheral clock frequency, see [`I2CSetup::i2c_freq`].
+    ///
+    /// This is the maximum over all bus modes. In each mode the frequency is
+    /// bounded by the minimum allowed value of the CCR.CCR field, which is 4
+    /// in Standard-mode and 1 in Fast-mode, and by the mode limit of 100 kHz
+    /// or 400 kHz respectively.
+    #[must_use]
+    pub fn max_scl_frequency(&self) -> u32 {
+        let pclk_hz = self.pclk_hz();
+        [I2CMode::Sm1, I2CMode::Fm2, I2CMode::Fm169]
+            .iter()
+            .map(|mode| mode.max_scl_frequency(pclk_hz))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn pclk_hz(&self) -> u32 {
+        self.config.i2c_freq * 1_000_000
+    }
+
+    /// Changes the SCL timings at runtime.
+    ///
+    /// The peripheral is disabled, the new values are written to CR2.FREQ,
+    /// CCR, and TRISE, and the peripheral is enabled again. The values have
+    /// the same meaning as [`I2CSetup::i2c_freq`], [`I2CSetup::i2c_presc`],
+    /// [`I2CSetup::i2c_trise`], and [`I2CSetup::i2c_mode`], and are kept in
+    /// the [`I2CDrv::config`] to be re-applied by [`I2CDrv::swrst`].
+    ///
+    /// The bus must be idle before switching, i.e. no other master should be
+    /// using the bus. This method blocks until the previous Stop signal
+    /// generation is finished, calling the watchdog hook. A master or slave
+    /// session borrows the driver, so this method can't be called while a
+    /// session is active.
+    ///
+    /// # Errors
+    ///
+    /// If the values are out of range, see [`I2CConfig::validate`], or
+    /// [`I2CConfigError::BusBusy`] if the peripheral is still in master mode,
+    /// e.g. a session was leaked without generating a Stop signal. The
+    /// timings are left unchanged in this case.
+    pub fn set_timings(
+        &mut self,
+        i2c_freq: u32,
+        i2c_presc: u32,
+        i2c_trise: u32,
+        i2c_mode: I2CMode,
+    ) -> Result<(), I2CConfigError> {
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        if self.i2c.i2c_sr2.msl().read_bit() {
+            return Err(I2CConfigError::BusBusy);
+        }
+        let config = I2CConfig { i2c_freq, i2c_presc, i2c_trise, i2c_mode, ..self.config };
+        config.validate()?;
+        self.config = config;
+        let I2CConfig { i2c_anfoff, i2c_dnf, i2c_smbus, .. } = config;
+        setup_i2c(
+            &mut self.i2c,
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            i2c_anfoff,
+            i2c_dnf,
+            i2c_smbus,
+            true,
+        );
+        Ok(())
+    }
+
+    /// Sets the clock for the address acknowledge timing capture.
+    ///
+    /// `clock` should return a free-running tick counter, e.g. the DWT cycle
+    /// counter or a timer counter register. The counter is allowed to wrap
+    /// around, but the acknowledge time must fit into a single period. The
+    /// clock is read from the task setting the Start bit, and from the I²C
+    /// event interrupt handler.
+    ///
+    /// Capturing is disabled until the clock is set.
+    #[cfg(feature = "ack-timing")]
+    pub fn set_ack_clock(&mut self, clock: fn() -> u32) {
+        self.ack_clock = Some(clock);
+    }
+
+    /// Returns the address acknowledge timing statistics collected since the
+    /// last [`I2CDrv::reset_ack_stats`] call.
+    #[cfg(feature = "ack-timing")]
+    #[must_use]
+    pub fn ack_stats(&self) -> I2CAckStats {
+        self.ack_timing.stats()
+    }
+
+    /// Resets the address acknowledge timing statistics.
+    #[cfg(feature = "ack-timing")]
+    pub fn reset_ack_stats(&mut self) {
+        self.ack_timing.reset();
+    }
+
+    /// Sets the length threshold for interrupt-driven transfers.
+    ///
+    /// Write and read operations shorter than `len` bytes are performed by the
+    /// I²C event interrupt handler, like in [`I2CIntDrv`](crate::I2CIntDrv),
+    /// instead of the DMA streams. Setting up a DMA stream costs more than
+    /// handling a few byte interrupts, so this reduces the latency of short
+    /// command transfers, while long data transfers still go through DMA.
+    /// Triggered operations always use DMA, and empty operations never use it.
+    ///
+    /// The default threshold is zero, which means all non-empty transfers use
+    /// DMA.
+    pub fn set_int_threshold(&mut self, len: usize) {
+        self.int_threshold = len;
+    }
+
+    /// Sets the number of times a master operation is restarted after losing
+    /// the arbitration to another master.
+    ///
+    /// On a multi-master bus, [`I2CMaster::write`](crate::I2CMaster::write)
+    /// and [`I2CMaster::read`](crate::I2CMaster::read) detect the arbitration
+    /// loss, wait until the other master releases the bus, i.e. SR2.BUSY is
+    /// cleared, and restart the transfer from the Start signal, re-arming the
+    /// DMA stream. The wait yields to other fibers of the thread, and is
+    /// bounded by the session timeout, see
+    /// [`I2CMaster::set_timeout`](crate::I2CMaster::set_timeout). A backoff
+    /// delay before each restart can be set with
+    /// [`I2CMaster::set_retry_backoff`](crate::I2CMaster::set_retry_backoff),
+    /// so the masters don't collide again right away. Only the first operation of a session is restarted: after a
+    /// repeated Start the bus has already been released between the
+    /// operations, so the error is returned to keep the session atomic. The
+    /// error hook is still called for every arbitration loss.
+    ///
+    /// The default count is zero, which means [`I2CError::ArbitrationLost`]
+    /// is returned right away. When the retries are exhausted, the error is
+    /// returned as well.
+    pub fn set_arbitration_retries(&mut self, count: u8) {
+        self.arb_retries = count;
+    }
+
+    /// Sets the number of consecutive NACKs from the same slave, after which
+    /// it's considered absent.
+    ///
+    /// This tells a transiently busy device from a removed or failed one. The
+    /// driver counts the operations with each slave address, which fail with
+    /// [`I2CError::Nack`], either on the address or on a data byte. Once the
+    /// count reaches `count`, the operations fail with
+    /// [`I2CError::DeviceGone`] instead. Any successful operation with the
+    /// address resets its count, and [`I2CDrv::reset_nack_counts`] resets all
+    /// of them, e.g. after the device is re-attached. The counts are kept for
+    /// up to 8 addresses at the same time. When more addresses fail, the count
+    /// with the fewest NACKs is dropped. 10-bit addresses are not tracked.
+    ///
+    /// The default count is zero, which disables the tracking. Setting a new
+    /// count resets all the counts.
+    pub fn set_nack_threshold(&mut self, count: u8) {
+        self.nack_counts.set_threshold(count);
+    }
+
+    /// Resets the consecutive NACK counts of all slave addresses, see
+    /// [`I2CDrv::set_nack_threshold`].
+    pub fn reset_nack_counts(&mut self) {
+        self.nack_counts.reset();
+    }
+
+    /// Sets the SMBus alert bit (CR1.ALERT).
+    ///
+    /// The alert uses the SMBA pin, which should be configured as the
+    /// alternate function open-drain output, and shared by all devices on the
+    /// bus as a wired-AND line with an external pull-up resistor.
+    ///
+    /// * In the SMBus Device mode, setting the bit drives SMBA low to signal
+    ///   the host, and makes the peripheral acknowledge the Alert Response
+    ///   Address (0x0C). Clear the bit once the host has serviced the alert.
+    /// * In the SMBus Host mode, setting the bit enables the SMBA pin, and an
+    ///   alert from a device is reported by [`I2CDrv::take_smbus_alert`]. The
+    ///   host finds the alerting device by reading a byte from the Alert
+    ///   Response Address.
+    ///
+    /// The bit is cleared by the hardware when the peripheral is disabled,
+    /// e.g. by [`I2CDrv::swrst`]. It has no effect outside of the SMBus mode,
+    /// see [`I2CSetup::i2c_smbus`].
+    pub fn set_smbus_alert(&mut self, alert: bool) {
+        self.i2c.i2c_cr1.modify_reg(|r, v| {
+            if alert {
+                r.alert().set(v); // drive SMBA low / SMBA enabled
+            } else {
+                r.alert().clear(v); // release SMBA / SMBA disabled
+            }
+        });
+    }
+
+    /// Returns `true` once after an SMBus alert (SR1.SMBALERT).
+    ///
+    /// In the SMBus Host mode, it's an alert signaled by a device on the SMBA
+    /// pin. In the SMBus Device mode, it's the host reading the Alert Response
+    /// Address while this device asserts the alert. The flag is cleared by
+    /// the error interrupt handler, and latched until this method is called.
+    /// See [`I2CDrv::set_smbus_alert`].
+    pub fn take_smbus_alert(&self) -> bool {
+        self.smbus_alert.swap(false, Ordering::AcqRel)
+    }
+
+    /// Sets a default timeout for the master operations.
+    ///
+    /// It's used by the sessions, which don't have their own timeout set with
+    /// [`I2CMaster::set_timeout`], including the sessions of the register
+    /// access methods, e.g. [`I2CDrv::read_reg`], and by
+    /// [`I2CDrv::read_reg_byte`]. See [`I2CMaster::set_timeout`] for details.
+    pub fn set_timeout<F: FnMut() -> T + Send + 'static, T: Future<Output = ()> + 'static>(
+        &mut self,
+        mut timeout: F,
+    ) {
+        self.timeout = Some(Box::new(move || Box::pin(timeout())));
+    }
+
+    /// Removes the timeout set with [`I2CDrv::set_timeout`].
+    pub fn clear_timeout(&mut self) {
+        self.timeout = None;
+    }
+
+    /// Enables or disables the dry-run mode.
+    ///
+    /// In the dry-run mode, session operations validate their arguments and
+    /// record the intended bus operations instead of driving the hardware.
+    /// Read operations leave the session buffer intact. This allows to test
+    /// the transaction sequences of a device driver. Enabling the mode clears
+    /// the recorded operations.
+    ///
+    /// # Panics
+    ///
+    /// The operations panic in the dry-run mode if the slave address is out of
+    /// the 7-bit range.
+    #[cfg(feature = "dry-run")]
+    pub fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Takes the operations recorded in the dry-run mode since the last call.
+    #[cfg(feature = "dry-run")]
+    pub fn take_dry_run_ops(&mut self) -> Vec<I2COp> {
+        self.dry_run.as_mut().map(core::mem::take).unwrap_or_default()
+    }
+
+    /// Reprograms the DMA channel selection of the Tx and Rx streams.
+    ///
+    /// The new channel numbers have the same meaning as
+    /// [`I2CSetup::dma_tx_ch`] and [`I2CSetup::dma_rx_ch`].
+    ///
+    /// # Panics
+    ///
+    /// If a channel number is out of range, or a stream is still enabled by
+    /// an unfinished transfer.
+    pub fn remap_dma(&mut self, tx_ch: u32, rx_ch: u32) {
+        assert!(tx_ch < 8 && rx_ch < 8, "DMA channel out of range");
+        assert!(
+            !self.dma_tx.dma_ccr.en().read_bit() && !self.dma_rx.dma_ccr.en().read_bit(),
+            "DMA stream is active"
+        );
+        self.dma_tx.dma_ccr.modify_reg(|r, v| r.chsel().write(v, tx_ch)); // channel selection
+        self.dma_rx.dma_ccr.modify_reg(|r, v| r.chsel().write(v, rx_ch)); // channel selection
+    }
+
+    /// Changes the priority levels of the Tx and Rx streams, e.g. to
+    /// temporarily favor the I²C transfers over another DMA user.
+    ///
+    /// The new levels have the same meaning as [`I2CSetup::dma_tx_pl`] and
+    /// [`I2CSetup::dma_rx_pl`], and are kept in [`I2CDrv::config`]. The PL
+    /// field can be written only while a stream is disabled, so this should
+    /// be called between the sessions.
+    ///
+    /// # Panics
+    ///
+    /// If a priority level is out of range, or a stream is still enabled by
+    /// an unfinished transfer.
+    pub fn set_dma_priority(&mut self, tx_pl: u32, rx_pl: u32) {
+        assert!(tx_pl <= 0b11 && rx_pl <= 0b11, "DMA priority level out of range");
+        assert!(
+            !self.dma_tx.dma_ccr.en().read_bit() && !self.dma_rx.dma_ccr.en().read_bit(),
+            "DMA stream is active"
+        );
+        self.dma_tx.dma_ccr.modify_reg(|r, v| r.pl().write(v, tx_pl)); // priority level
+        self.dma_rx.dma_ccr.modify_reg(|r, v| r.pl().write(v, rx_pl)); // priority level
+        self.config.dma_tx_pl = tx_pl;
+        self.config.dma_rx_pl = rx_pl;
+    }
+
+    /// Returns the current interrupt flags of the DMA streams.
+    ///
+    /// This is an advanced diagnostic method, which can help to find out why a
+    /// transfer has stalled. The flags are normally handled by the driver
+    /// itself.
+    #[must_use]
+    pub fn dma_flags(&self) -> I2CDmaFlags {
+        I2CDmaFlags { tx: I2CDmaChFlags::load(&self.dma_tx), rx: I2CDmaChFlags::load(&self.dma_rx) }
+    }
+
+    /// Clears the DMA stream interrupt flags, which are set in `flags`.
+    ///
+    /// This is an advanced diagnostic method. Flags not set in `flags` are left
+    /// intact.
+    pub fn clear_dma_flags(&mut self, flags: I2CDmaFlags) {
+        flags.tx.clear(&self.dma_tx);
+        flags.rx.clear(&self.dma_rx);
+    }
+
+    /// Reads the peripheral and the DMA stream state, and returns an
+    /// assessment of why a transfer doesn't complete.
+    ///
+    /// This is an advanced diagnostic method. The registers are only read, and
+    /// SR2 is read before SR1, so that the ADDR flag is not cleared. The result
+    /// is a snapshot, and can be inconsistent if the transfer progresses
+    /// meanwhile.
+    #[must_use]
+    pub fn diagnose_hang(&self) -> I2CHangDiagnosis {
+        I2CHangDiagnosis::load(&self.i2c, &self.dma_tx, &self.dma_rx)
+    }
+
+    /// Reads the I²C status registers, and returns a snapshot of their flags.
+    ///
+    /// This is a diagnostic method, e.g. to see the BUSY, MSL, and TRA flags
+    /// of a wedged bus. The registers are only read, so that no flags are
+    /// cleared. See also [`I2CDrv::diagnose_hang`] for an interpretation of
+    /// the state.
+    #[must_use]
+    pub fn status(&self) -> I2CStatus {
+        I2CStatus::load(&self.i2c)
+    }
+
+    pub(crate) unsafe fn write(
+        &mut self,
+        addr: I2CAddr,
+        buf_tx: &[u8],
+    ) -> impl Future<Output = ()> {
+        match addr {
+            I2CAddr::Bit7(addr) if buf_tx.is_empty() || buf_tx.len() < self.int_threshold => {
+                self.i2c.i2c_cr2.dmaen().clear_bit(); // DMA requests disable
+                write_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, buf_tx).left_future()
+            }
+            _ => {
+                assert!(!buf_tx.is_empty(), "Empty 10-bit transfer");
+                let (addr, low) = addr.bytes(false);
+                let pending = self.dma_tx(buf_tx);
+                self.start(addr, low, Ack::Off, pending).right_future()
+            }
+        }
+    }
+
+    pub(crate) unsafe fn read(
+        &mut self,
+        addr: I2CAddr,
+        buf_rx: &mut [u8],
+    ) -> impl Future<Output = ()> {
+        match addr {
+            I2CAddr::Bit7(addr) if buf_rx.is_empty() || buf_rx.len() < self.int_threshold => {
+                self.i2c.i2c_cr2.dmaen().clear_bit(); // DMA requests disable
+                read_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, buf_rx).left_future()
+            }
+            _ => {
+                assert!(!buf_rx.is_empty(), "Empty 10-bit transfer");
+                let (addr, low) = addr.bytes(true);
+                let dma_rx = self.dma_rx(buf_rx, true);
+                self.start(addr, low, Ack::rx(buf_rx.len(), true), None)
+                    .then(|()| dma_rx)
+                    .right_future()
+            }
+        }
+    }
+
+    /// Programs the DMA Rx stream for a read of `buf_rx` from the slave at the
+    /// address `addr`, which is started later by [`I2CDrv::read_prearmed`].
+    /// Returns `None` if the read doesn't use the DMA.
+    ///
+    /// The stream is not enabled, so it's safe to call this while a write is
+    /// in progress. If the returned value is dropped instead of being passed
+    /// to [`I2CDrv::read_prearmed`], the stream is left disabled.
+    pub(crate) unsafe fn prearm_rx(
+        &mut self,
+        addr: I2CAddr,
+        buf_rx: &mut [u8],
+    ) -> Option<RxPrearm<impl Future<Output = ()>>> {
+        if buf_rx.is_empty()
+            || matches!(addr, I2CAddr::Bit7(_) if buf_rx.len() < self.int_threshold)
+        {
+            return None;
+        }
+        let armed = Arc::new(AtomicBool::new(false));
+        let len = buf_rx.len();
+        let chunks = RxChunks::new(len, true);
+        let (future, rest_last) = self.program_dma_rx(buf_rx, chunks, Some(Arc::clone(&armed)));
+        Some(RxPrearm { armed, future, len, rest_last })
+    }
+
+    /// Starts the read pre-armed by [`I2CDrv::prearm_rx`]. Only the stream
+    /// enable and the Start signal are left for the critical path.
+    pub(crate) unsafe fn read_prearmed<T: Future<Output = ()>>(
+        &mut self,
+        addr: I2CAddr,
+        prearm: RxPrearm<T>,
+    ) -> impl Future<Output = ()> {
+        let RxPrearm { armed, future, len, rest_last } = prearm;
+        let (addr, low) = addr.bytes(true);
+        armed.store(true, Ordering::Release);
+        self.enable_dma_rx(rest_last);
+        self.start(addr, low, Ack::rx(len, true), None).then(|()| future)
+    }
+
+    pub(crate) unsafe fn write_pec(&mut self, addr: u8, buf_tx: &[u8]) -> impl Future<Output = ()> {
+        assert!(
+            !buf_tx.is_empty() && buf_tx.len() <= DMA_CHUNK,
+            "PEC transfer length out of range"
+        );
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.enpec().set(v)); // PEC calculation enable
+        // the PEC byte is sent by the hardware after the DMA EOT
+        let pending = self.dma_tx(buf_tx);
+        self.start(addr << 1, None, Ack::Off, pending)
+    }
+
+    pub(crate) unsafe fn read_pec(
+        &mut self,
+        addr: u8,
+        buf_rx: &mut [u8],
+    ) -> impl Future<Output = ()> {
+        assert!(buf_rx.len() > 1 && buf_rx.len() <= DMA_CHUNK, "PEC transfer length out of range");
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.enpec().set(v)); // PEC calculation enable
+        // the byte after the DMA EOT-1 is checked by the hardware as the PEC byte
+        let dma_rx = self.dma_rx(buf_rx, true);
+        self.start(addr << 1 | 1, None, Ack::rx(buf_rx.len(), true), None).then(|()| dma_rx)
+    }
+
+    /// Returns the read future, and a future, which resolves to `true` when
+    /// the first half of `buf_rx` is filled, or to `false` if the stream is
+    /// disabled before that.
+    pub(crate) unsafe fn read_half(
+        &mut self,
+        addr: u8,
+        buf_rx: &mut [u8],
+    ) -> (impl Future<Output = ()>, impl Future<Output = bool>) {
+        assert!(buf_rx.len() > 1 && buf_rx.len() <= DMA_CHUNK, "Read length out of range");
+        let len = buf_rx.len();
+        let dma_ccr = self.dma_rx.dma_ccr;
+        let dma_ifcr_chtif = self.dma_rx.dma_ifcr_chtif;
+        let dma_isr_htif = self.dma_rx.dma_isr_htif;
+        let half = self.dma_rx_int.add_future(fib::new_fn(move || {
+            let filled = dma_isr_htif.read_bit();
+            if filled {
+                // half transfer interrupt flag
+                dma_ifcr_chtif.set_bit(); // clear half transfer interrupt flag
+            } else if dma_ccr.en().read_bit() {
+                return fib::Yielded(());
+            }
+            // the half is filled, or the stream is disabled by the error interrupt handler
+            dma_ccr.modify_reg(|r, v| r.htie().clear(v)); // half transfer interrupt disable
+            fib::Complete(filled)
+        }));
+        let dma_rx = self.dma_rx(buf_rx, true);
+        self.dma_rx.dma_ccr.modify_reg(|r, v| r.htie().set(v)); // half transfer interrupt enable
+        (self.start(addr << 1 | 1, None, Ack::rx(len, true), None).then(|()| dma_rx), half)
+    }
+
+    /// Writes `buf_tx` with the PEC calculation enabled, but without sending
+    /// the PEC byte, so that it's included in the PEC of the following read.
+    pub(crate) unsafe fn write_no_pec(
+        &mut self,
+        addr: u8,
+        buf_tx: &[u8],
+    ) -> impl Future<Output = ()> {
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.enpec().set(v)); // PEC calculation enable
+        // the PEC byte is sent automatically only on the DMA EOT
+        self.i2c.i2c_cr2.dmaen().clear_bit(); // DMA requests disable
+        write_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, buf_tx)
+    }
+
+    /// Saves the calculated PEC, disables the PEC calculation, and reports a
+    /// PEC error, which is not yet handled by the error interrupt handler.
+    pub(crate) fn end_pec(&mut self) {
+        // SR2.PEC holds the calculated PEC only while ENPEC is set
+        self.last_pec = self.i2c.i2c_sr2.pec().read_bits() as u8;
+        self.i2c.i2c_cr1.modify_reg(|r, v| r.enpec().clear(v)); // PEC calculation disable
+        if self.i2c.i2c_sr1.pecerr().read_bit() {
+            self.err_release().release(I2CError::Pec);
+        }
+    }
+
+    pub(crate) fn slave_listen(&mut self) -> impl Future<Output = I2CSlaveReq> {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let i2c_sr1 = self.i2c.i2c_sr1;
+        let i2c_sr2 = self.i2c.i2c_sr2;
+        let err = Arc::clone(&self.err);
+        let future = self.i2c_ev.add_future(fib::new_fn(move || {
+            if err.is_set() {
+                // the error interrupt handler has already released the lines
+                return fib::Complete(I2CSlaveReq {
+                    read: false,
+                    dual: false,
+                    general_call: false,
+                });
+            }
+            let sr1_val = i2c_sr1.load_val();
+            if i2c_sr1.stopf().read(&sr1_val) {
+                // stop condition detected after the previous request
+                i2c_cr1.modify_reg(|_, _| {}); // clear STOPF by writing CR1 after reading SR1
+            }
+            if i2c_sr1.addr().read(&sr1_val) {
+                // reading SR2 after SR1 clears ADDR
+                let sr2_val = i2c_sr2.load_val();
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(I2CSlaveReq {
+                    read: i2c_sr2.tra().read(&sr2_val),
+                    dual: i2c_sr2.dualf().read(&sr2_val),
+                    general_call: i2c_sr2.gencall().read(&sr2_val),
+                });
+            }
+            fib::Yielded(())
+        }));
+        self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+        future
+    }
+
+    pub(crate) unsafe fn slave_receive(
+        &mut self,
+        buf_rx: &mut [u8],
+    ) -> impl Future<Output = usize> {
+        assert!(
+            !buf_rx.is_empty() && buf_rx.len() <= DMA_CHUNK,
+            "Slave transfer length out of range"
+        );
+        let len = buf_rx.len();
+        let dma_ccr = self.dma_rx.dma_ccr;
+        let dma_cndtr = self.dma_rx.dma_cndtr;
+        let dma_rx = self.dma_rx(buf_rx, false);
+        self.slave_end(false).then(move |()| {
+            // disabling the stream sets TCIF, which completes the DMA future
+            dma_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+            dma_rx.map(move |()| len - dma_cndtr.ndt().read_bits() as usize)
+        })
+    }
+
+    pub(crate) unsafe fn slave_transmit(&mut self, buf_tx: &[u8]) -> impl Future<Output = ()> {
+        assert!(
+            !buf_tx.is_empty() && buf_tx.len() <= DMA_CHUNK,
+            "Slave transfer length out of range"
+        );
+        let dma_ccr = self.dma_tx.dma_ccr;
+        self.dma_tx(buf_tx);
+        self.slave_end(true).map(move |()| {
+            dma_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+        })
+    }
+
+    pub(crate) fn slave_serve_tx<F: FnMut(usize) -> u8 + Send + 'static>(
+        &mut self,
+        mut serve: F,
+    ) -> impl Future<Output = usize> {
+        let i2c_dr = self.i2c.i2c_dr;
+        self.slave_serve(move |i2c_sr1, sr1_val, count| {
+            // the acknowledge failure ends the read, it's handled by the error
+            // interrupt handler
+            if i2c_sr1.tx_e().read(sr1_val) && !i2c_sr1.af().read(sr1_val) {
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(serve(count)))); // 8-bit data register
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    pub(crate) fn slave_serve_rx<F: FnMut(usize, u8) + Send + 'static>(
+        &mut self,
+        mut serve: F,
+    ) -> impl Future<Output = usize> {
+        let i2c_dr = self.i2c.i2c_dr;
+        self.slave_serve(move |i2c_sr1, sr1_val, count| {
+            if i2c_sr1.rx_ne().read(sr1_val) {
+                serve(count, i2c_dr.dr().read_bits() as u8); // 8-bit data register
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    pub(crate) fn set_general_call(&mut self, enabled: bool) {
+        if enabled {
+            self.i2c.i2c_cr1.engc().set_bit(); // general call enabled
+        } else {
+            self.i2c.i2c_cr1.engc().clear_bit(); // general call disabled
+        }
+    }
+
+    pub(crate) fn slave_disable(&mut self) {
+        self.i2c.i2c_cr2.itevten().clear_bit(); // event interrupt disable
+        self.i2c.i2c_cr1.modify_reg(|r, v| {
+            r.engc().clear(v); // general call disabled
+            r.ack().clear(v); // acknowledge disable
+        });
+        self.i2c.i2c_oar2.store_reg(|r, v| r.endual().clear(v)); // dual addressing mode disable
+        self.i2c.i2c_oar1.store_bits(1 << 14); // bit 14 should always be kept at 1 by software
+    }
+
+    pub(crate) unsafe fn write_triggered<T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        buf_tx: &[u8],
+        trigger: T,
+    ) -> impl Future<Output = ()> {
+        let pending = self.dma_tx(buf_tx);
+        self.start_triggered(addr << 1, Ack::Off, pending, trigger)
+    }
+
+    pub(crate) unsafe fn read_triggered<T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        buf_rx: &mut [u8],
+        trigger: T,
+    ) -> impl Future<Output = ()> {
+        let dma_rx = self.dma_rx(buf_rx, true);
+        self.start_triggered(addr << 1 | 1, Ack::rx(buf_rx.len(), true), None, trigger)
+            .then(|()| dma_rx)
+    }
+
+    pub(crate) unsafe fn read_segment(
+        &mut self,
+        addr: Option<u8>,
+        buf_rx: &mut [u8],
+        last: bool,
+    ) -> impl Future<Output = ()> {
+        let (ack, chunks) = rx_segment(buf_rx.len(), addr.is_some(), last);
+        let (dma_rx, rest_last) = self.program_dma_rx(buf_rx, chunks, None);
+        self.enable_dma_rx(rest_last);
+        match addr.zip(ack) {
+            Some((addr, ack)) => {
+                self.start(addr << 1 | 1, None, ack, None).then(|()| dma_rx).left_future()
+            }
+            None => dma_rx.right_future(),
+        }
+    }
+
+    pub(crate) unsafe fn write_segment(
+        &mut self,
+        addr: Option<u8>,
+        buf_tx: &[u8],
+    ) -> impl Future<Output = ()> {
+        if let Some(addr) = addr {
+            return self.write(I2CAddr::Bit7(addr), buf_tx).left_future();
+        }
+        // SCL is stretched with BTF set until the DMA writes the next byte
+        let pending = self.dma_tx(buf_tx);
+        let future = self.arm_from(I2CEvState::Data, false, 0, 0, pending);
+        self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+        future.right_future()
+    }
+
+    pub(crate) unsafe fn write_held(
+        &mut self,
+        addr: u8,
+        buf_tx: &[u8],
+    ) -> impl Future<Output = Option<Arc<AtomicBool>>> {
+        let pending = self.dma_tx(buf_tx);
+        self.start_held(addr << 1, Ack::Off).map(move |()| pending)
+    }
+
+    pub(crate) unsafe fn read_held(
+        &mut self,
+        addr: u8,
+        buf_rx: &mut [u8],
+    ) -> impl Future<Output = impl Future<Output = ()>> {
+        let dma_rx = self.dma_rx(buf_rx, true);
+        self.start_held(addr << 1 | 1, Ack::rx(buf_rx.len(), true)).map(move |()| dma_rx)
+    }
+
+    pub(crate) unsafe fn read_stream(
+        &mut self,
+        addr: u8,
+        buf0: &mut [u8],
+        buf1: &mut [u8],
+    ) -> (impl Future<Output = ()>, impl Stream<Item = NonZeroUsize>) {
+        debug_assert_eq!(buf0.len(), buf1.len());
+        let dma_ccr = self.dma_rx.dma_ccr;
+        let dma_ifcr_ctcif = self.dma_rx.dma_ifcr_ctcif;
+        let dma_isr_tcif = self.dma_rx.dma_isr_tcif;
+        let dma_err = DmaErr::new(&self.dma_rx, self.on_error);
+        let stream = self.dma_rx_int.add_saturating_pulse_stream(fib::new_fn(move || {
+            let val = dma_isr_tcif.load_val();
+            dma_err.handle(&val);
+            if dma_isr_tcif.read(&val) {
+                // transfer complete interrupt flag
+                dma_ifcr_ctcif.set_bit(); // clear transfer complete interrupt flag
+                if !dma_ccr.en().read_bit() {
+                    // the stream is disabled by the end of the read, or by the error
+                    // interrupt handler
+                    return fib::Complete(None);
+                }
+                return fib::Yielded(Some(1));
+            }
+            fib::Yielded(None)
+        }));
+        self.dma_rx.dma_cm0ar.store_reg(|r, v| {
+            r.m0a().write(v, buf0.as_mut_ptr() as u32); // memory 0 address
+        });
+        self.dma_rx.dma_cm1ar.store_reg(|r, v| {
+            r.m1a().write(v, buf1.as_mut_ptr() as u32); // memory 1 address
+        });
+        self.dma_rx.dma_cndtr.store_reg(|r, v| {
+            r.ndt().write(v, buf0.len() as u32); // number of data items to transfer
+        });
+        self.dma_rx.dma_ccr.modify_reg(|r, v| {
+            r.ct().clear(v); // current target is memory 0
+            r.dbm().set(v); // double buffer mode
+            r.en().set(v); // stream enable
+        });
+        self.i2c.i2c_cr2.modify_reg(|r, v| {
+            r.last().clear(v); // next DMA EOT is not the last transfer
+            r.dmaen().set(v); // DMA requests enable
+        });
+        (self.start(addr << 1 | 1, None, Ack::On, None), stream)
+    }
+
+    /// Makes the buffer being filled by [`I2CDrv::read_stream`] the last one.
+    pub(crate) fn read_stream_last(&mut self) {
+        self.i2c.i2c_cr2.last().set_bit(); // next DMA EOT is the last transfer
+    }
+
+    /// Disables the stream of [`I2CDrv::read_stream`] after the last buffer.
+    pub(crate) fn read_stream_end(&mut self) {
+        self.dma_rx.dma_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+    }
+
+    /// Restores the single buffer mode after [`I2CDrv::read_stream`] ended.
+    pub(crate) fn read_stream_reset(&mut self) {
+        self.dma_rx.dma_ccr.modify_reg(|r, v| r.dbm().clear(v)); // no buffer switching
+    }
+
+    pub(crate) fn release_addr(
+        &mut self,
+        addr: u8,
+        pending: Option<Arc<AtomicBool>>,
+    ) -> impl Future<Output = ()> {
+        self.i2c.i2c_sr1.load_val(); // reading SR2 after SR1 clears ADDR
+        match check_direction::<I2C>(self.i2c.i2c_sr2, addr) {
+            Ok(true) => {
+                let future = self.arm_from(I2CEvState::Data, false, addr, 0, pending);
+                self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+                future.left_future()
+            }
+            Ok(false) => future::ready(()).right_future(),
+            Err(error) => {
+                self.abort(error);
+                future::ready(()).right_future()
+            }
+        }
+    }
+
+    async fn read_reg_bytes(
+        &mut self,
+        addr: u8,
+        reg: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), I2CError> {
+        let mut block = vec![0; reg.len() + buf.len()].into_boxed_slice();
+        block[..reg.len()].copy_from_slice(reg);
+        let mut master = self.master(block);
+        master.write_read(addr, ..reg.len(), reg.len()..).await?;
+        let block = master.stop();
+        buf.copy_from_slice(&block[reg.len()..]);
+        Ok(())
+    }
+
+    async fn write_reg_bytes(&mut self, addr: u8, reg: &[u8], data: &[u8]) -> Result<(), I2CError> {
+        let mut block = Vec::with_capacity(reg.len() + data.len());
+        block.extend_from_slice(reg);
+        block.extend_from_slice(data);
+        let mut master = self.master(block.into_boxed_slice());
+        master.write(addr, ..).await?;
+        drop(master.stop());
+        Ok(())
+    }
+
+    /// Returns and clears the error reported by the error interrupt handler
+    /// since the last call.
+    pub(crate) fn take_error(&mut self) -> Option<I2CError> {
+        self.err.take()
+    }
+
+    pub(crate) fn has_error(&self) -> bool {
+        self.err.is_set()
+    }
+
+    /// Discards an error left from an earlier session.
+    pub(crate) fn clear_error(&mut self) {
+        self.err.take();
+    }
+
+    /// Returns a future, which resolves when the bus is not busy, i.e. no
+    /// other master holds it. The bus release doesn't raise an interrupt, so
+    /// the future wakes itself up immediately while the bus is busy.
+    pub(crate) fn bus_idle(&self) -> impl Future<Output = ()> {
+        let i2c_sr2 = self.i2c.i2c_sr2;
+        future::poll_fn(move |cx| {
+            if i2c_sr2.busy().read_bit() {
+                // bus busy
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+    }
+
+    /// Starts the default timeout of [`I2CDrv::set_timeout`].
+    pub(crate) fn default_timeout(&mut self) -> Option<Pin<Box<dyn Future<Output = ()>>>> {
+        self.timeout.as_mut().map(|timeout| timeout())
+    }
+
+    /// Accounts the `result` of an operation with the slave at the address
+    /// `addr`, see [`I2CDrv::set_nack_threshold`].
+    pub(crate) fn track_nacks(
+        &mut self,
+        addr: u8,
+        result: Result<(), I2CError>,
+    ) -> Result<(), I2CError> {
+        self.nack_counts.track(addr, result)
+    }
+
+    pub(crate) fn last_pec(&self) -> u8 {
+        self.last_pec
+    }
+
+    pub(crate) fn arbitration_retries(&self) -> u8 {
+        self.arb_retries
+    }
+
+    /// Takes an arbitration loss error of the last transfer, so the transfer
+    /// can be restarted. Other errors are left in place.
+    pub(crate) fn take_arbitration_lost(&mut self) -> bool {
+        self.take_restartable(|err| err == I2CError::ArbitrationLost)
+    }
+
+    /// Takes a recoverable error of the last transfer, see
+    /// [`I2CError::is_recoverable`], so the transfer can be restarted. Other
+    /// errors are left in place.
+    pub(crate) fn take_recoverable(&mut self) -> bool {
+        self.take_restartable(I2CError::is_recoverable)
+    }
+
+    fn take_restartable(&mut self, restartable: impl FnOnce(I2CError) -> bool) -> bool {
+        match self.err.take() {
+            Some(err) if restartable(err) => true,
+            Some(err) => {
+                self.err.set(err);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Aborts the transfer in progress with `error`, the same way as the
+    /// error interrupt handler does.
+    pub(crate) fn abort(&mut self, error: I2CError) {
+        self.err_release().release(error);
+        // complete the event futures
+        unsafe { I2CEv::wakeup_unchecked() };
+    }
+
+    /// Aborts the transfer of an operation future dropped or leaked before
+    /// completion, the same way as [`I2CDrv::abort`], but without calling the
+    /// error hook. The fibers of the transfer are completed with a timeout
+    /// error. Returns after the DMA Rx stream stops writing to the buffer.
+    pub(crate) fn cancel(&self) {
+        self.err_release().cancel::<I2CEv>(self.watchdog);
+    }
+
+    /// Returns a guard, which cancels the transfer with [`I2CDrv::cancel`]
+    /// when dropped before [`CancelGuard::disarm`], i.e. when the operation
+    /// future is dropped before completion.
+    pub(crate) fn cancel_guard(&self) -> CancelGuard<I2C, I2CEv, DmaTx, DmaRx> {
+        CancelGuard {
+            release: Some(self.err_release()),
+            watchdog: self.watchdog,
+            i2c_ev: PhantomData,
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        #[cfg(feature = "dry-run")]
+        if self.dry_run(I2COp::Stop) {
+            return;
+        }
+        self.i2c.i2c_cr1.stop().set_bit(); // stop generation
+    }
+
+    #[cfg(feature = "dry-run")]
+    pub(crate) fn dry_run(&mut self, op: I2COp) -> bool {
+        dry_run::record(&mut self.dry_run, op)
+    }
+
+    #[cfg(feature = "dry-run")]
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    unsafe fn dma_tx(&mut self, buf_tx: &[u8]) -> Option<Arc<AtomicBool>> {
+        let (buf_tx, rest) = buf_tx.split_at(buf_tx.len().min(DMA_CHUNK));
+        self.dma_tx.dma_cm0ar.store_reg(|r, v| {
             r.m0a().write(v, buf_tx.as_ptr() as u32); // memory address
         });
+        debug_assert_eq!(
+            self.dma_tx.dma_cm0ar.m0a().read_bits(),
+            buf_tx.as_ptr() as u32,
+            "DMA memory address readback mismatch"
+        );
         self.dma_tx.dma_cndtr.store_reg(|r, v| {
             r.ndt().write(v, buf_tx.len() as u32); // number of data items to transfer
         });
+        self.err.add_sent(buf_tx.len());
         self.dma_tx.dma_ifcr_ctcif.set_bit(); // clear transfer complete interrupt flag
+        let pending = if rest.is_empty() { None } else { Some(self.chain_dma_tx(rest)) };
         self.dma_tx.dma_ccr.modify_reg(|r, v| r.en().set(v)); // stream enable
+        self.i2c.i2c_cr2.dmaen().set_bit(); // DMA requests enable
+        pending
     }
 
-    unsafe fn dma_rx(&mut self, buf_rx: &mut [u8]) -> impl Future<Output = ()> {
+    unsafe fn chain_dma_tx(&mut self, rest: &[u8]) -> Arc<AtomicBool> {
+        let pending = Arc::new(AtomicBool::new(true));
+        let fib_pending = Arc::clone(&pending);
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let dma_ccr = self.dma_tx.dma_ccr;
+        let dma_cm0ar = self.dma_tx.dma_cm0ar;
+        let dma_cndtr = self.dma_tx.dma_cndtr;
+        let dma_ifcr_ctcif = self.dma_tx.dma_ifcr_ctcif;
+        let dma_isr_tcif = self.dma_tx.dma_isr_tcif;
+        let err = Arc::clone(&self.err);
+        let mut ptr = rest.as_ptr() as usize;
+        let mut len = rest.len();
+        self.dma_tx_int.add_fn(move || {
+            if !dma_isr_tcif.read_bit() {
+                return fib::Yielded(());
+            }
+            // transfer complete interrupt flag
+            dma_ifcr_ctcif.set_bit(); // clear transfer complete interrupt flag
+            if dma_cndtr.ndt().read_bits() != 0 {
+                // the stream is disabled by the error interrupt handler
+                dma_ccr.modify_reg(|r, v| r.tcie().clear(v)); // transfer complete interrupt disable
+                fib_pending.store(false, Ordering::Release);
+                return fib::Complete(());
+            }
+            if len == 0 {
+                dma_ccr.modify_reg(|r, v| r.tcie().clear(v)); // transfer complete interrupt disable
+                fib_pending.store(false, Ordering::Release);
+                i2c_cr2.itevten().set_bit(); // event interrupt enable
+                return fib::Complete(());
+            }
+            let chunk = len.min(DMA_CHUNK);
+            dma_cm0ar.store_reg(|r, v| r.m0a().write(v, ptr as u32)); // memory address
+            dma_cndtr.store_reg(|r, v| r.ndt().write(v, chunk as u32)); // number of data items to transfer
+            err.add_sent(chunk);
+            ptr += chunk;
+            len -= chunk;
+            dma_ccr.modify_reg(|r, v| r.en().set(v)); // stream enable
+            i2c_cr2.itevten().set_bit(); // event interrupt enable
+            fib::Yielded(())
+        });
+        self.dma_tx.dma_ccr.modify_reg(|r, v| r.tcie().set(v)); // transfer complete interrupt enable
+        pending
+    }
+
+    unsafe fn dma_rx(&mut self, buf_rx: &mut [u8], last: bool) -> impl Future<Output = ()> {
+        let chunks = RxChunks::new(buf_rx.len(), last);
+        let (future, rest_last) = self.program_dma_rx(buf_rx, chunks, None);
+        self.enable_dma_rx(rest_last);
+        future
+    }
+
+    /// Programs the DMA Rx stream for `buf_rx`, split into `chunks`, without
+    /// enabling it. Returns the stream future, and whether the first chunk is
+    /// the last transfer.
+    ///
+    /// If `armed` is given, the stream future completes on the next DMA Rx
+    /// interrupt without touching the stream until `armed` is set, so that an
+    /// abandoned pre-armed stream doesn't consume the flags of a later one.
+    unsafe fn program_dma_rx(
+        &mut self,
+        buf_rx: &mut [u8],
+        mut chunks: RxChunks,
+        armed: Option<Arc<AtomicBool>>,
+    ) -> (impl Future<Output = ()>, bool) {
+        let (first, first_last) = chunks.next().unwrap_or_default();
+        let (buf_rx, rest) = buf_rx.split_at_mut(first);
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let dma_ccr = self.dma_rx.dma_ccr;
+        let dma_cm0ar = self.dma_rx.dma_cm0ar;
+        let dma_cndtr = self.dma_rx.dma_cndtr;
         let dma_ifcr_ctcif = self.dma_rx.dma_ifcr_ctcif;
-        let dma_isr_dmeif = self.dma_rx.dma_isr_dmeif;
-        let dma_isr_feif = self.dma_rx.dma_isr_feif;
         let dma_isr_tcif = self.dma_rx.dma_isr_tcif;
-        let dma_isr_teif = self.dma_rx.dma_isr_teif;
+        let dma_err = DmaErr::new(&self.dma_rx, self.on_error);
+        let mut ptr = rest.as_mut_ptr() as usize;
         let future = self.dma_rx_int.add_future(fib::new_fn(move || {
+            if armed.as_ref().map_or(false, |armed| !armed.load(Ordering::Acquire)) {
+                // the pre-armed stream is abandoned
+                return fib::Complete(());
+            }
             let val = dma_isr_tcif.load_val();
-            handle_dma_err::<DmaRx>(&val, dma_isr_dmeif, dma_isr_feif, dma_isr_teif);
+            dma_err.handle(&val);
             if dma_isr_tcif.read(&val) {
                 // transfer complete interrupt flag
                 dma_ifcr_ctcif.set_bit(); // clear transfer complete interrupt flag
-                fib::Complete(())
-            } else {
-                fib::Yielded(())
+                let (chunk, chunk_last) = match chunks.next() {
+                    Some(next) if dma_cndtr.ndt().read_bits() == 0 => next,
+                    _ => {
+                        // the last chunk is received, or the stream is disabled by the
+                        // error interrupt handler
+                        return fib::Complete(());
+                    }
+                };
+                dma_cm0ar.store_reg(|r, v| r.m0a().write(v, ptr as u32)); // memory address
+                dma_cndtr.store_reg(|r, v| r.ndt().write(v, chunk as u32)); // number of data items to transfer
+                ptr += chunk;
+                if chunk_last {
+                    i2c_cr2.last().set_bit(); // next DMA EOT is the last transfer
+                }
+                dma_ccr.modify_reg(|r, v| r.en().set(v)); // stream enable
             }
+            fib::Yielded(())
         }));
         self.dma_rx.dma_cm0ar.store_reg(|r, v| {
             r.m0a().write(v, buf_rx.as_mut_ptr() as u32); // memory address
         });
+        debug_assert_eq!(
+            self.dma_rx.dma_cm0ar.m0a().read_bits(),
+            buf_rx.as_mut_ptr() as u32,
+            "DMA memory address readback mismatch"
+        );
         self.dma_rx.dma_cndtr.store_reg(|r, v| {
             r.ndt().write(v, buf_rx.len() as u32); // number of data items to transfer
         });
+        (future, first_last)
+    }
+
+    /// Enables the DMA Rx stream programmed by [`I2CDrv::program_dma_rx`].
+    fn enable_dma_rx(&mut self, rest_last: bool) {
         self.dma_rx.dma_ccr.modify_reg(|r, v| r.en().set(v)); // stream enable
+        self.i2c.i2c_cr2.modify_reg(|r, v| {
+            if rest_last {
+                r.last().set(v); // next DMA EOT is the last transfer
+            } else {
+                r.last().clear(v); // next DMA EOT is not the last transfer
+            }
+            r.dmaen().set(v); // DMA requests enable
+        });
+    }
+
+    fn start(
+        &mut self,
+        addr: u8,
+        low: Option<u8>,
+        ack: Ack,
+        pending: Option<Arc<AtomicBool>>,
+    ) -> impl Future<Output = ()> {
+        let future = self.arm(addr, low, pending);
+        #[cfg(feature = "ack-timing")]
+        self.ack_timing.start(self.ack_clock);
+        set_start::<I2C>(self.i2c.i2c_cr1, ack);
+        self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+        future
+    }
+
+    fn start_held(&mut self, addr: u8, ack: Ack) -> impl Future<Output = ()> {
+        let future = self.arm_from(I2CEvState::Start, true, addr, 0, None);
+        #[cfg(feature = "ack-timing")]
+        self.ack_timing.start(self.ack_clock);
+        set_start::<I2C>(self.i2c.i2c_cr1, ack);
+        self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
         future
     }
 
-    fn start(&mut self, addr: u8, ack: bool) -> impl Future<Output = ()> {
+    fn start_triggered<T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        ack: Ack,
+        pending: Option<Arc<AtomicBool>>,
+        trigger: T,
+    ) -> impl Future<Output = ()> {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        #[cfg(feature = "ack-timing")]
+        let (ack_clock, ack_timing) = (self.ack_clock, Arc::clone(&self.ack_timing));
+        let err = Arc::clone(&self.err);
+        let future = self.arm(addr, None, pending);
+        async move {
+            futures::pin_mut!(trigger);
+            let fired = future::poll_fn(|cx| {
+                if err.is_set() {
+                    // the transfer is aborted before the trigger, e.g. by a timeout
+                    return Poll::Ready(false);
+                }
+                trigger.as_mut().poll(cx).map(|()| true)
+            })
+            .await;
+            if fired {
+                #[cfg(feature = "ack-timing")]
+                ack_timing.start(ack_clock);
+                set_start::<I2C>(i2c_cr1, ack);
+                i2c_cr2.itevten().set_bit(); // event interrupt enable
+            }
+            future.await;
+        }
+    }
+
+    fn arm(
+        &mut self,
+        addr: u8,
+        low: Option<u8>,
+        pending: Option<Arc<AtomicBool>>,
+    ) -> impl Future<Output = ()> {
+        match low {
+            Some(low) => self.arm_from(I2CEvState::Start10, false, addr, low, pending),
+            None => self.arm_from(I2CEvState::Start, false, addr, 0, pending),
+        }
+    }
+
+    fn arm_from(
+        &mut self,
+        mut state: I2CEvState,
+        hold: bool,
+        addr: u8,
+        low: u8,
+        pending: Option<Arc<AtomicBool>>,
+    ) -> impl Future<Output = ()> {
         let i2c_cr1 = self.i2c.i2c_cr1;
         let i2c_cr2 = self.i2c.i2c_cr2;
         let i2c_sr1 = self.i2c.i2c_sr1;
         let i2c_sr2 = self.i2c.i2c_sr2;
         let i2c_dr = self.i2c.i2c_dr;
-        let set_start = move || {
-            i2c_cr1.modify_reg(|r, v| {
-                if ack {
-                    r.ack().set(v); // acknowledge enable
-                } else {
-                    r.ack().clear(v); // acknowledge disable
-                }
-                r.start().set(v); // start generation
-            });
-        };
-        let repeated = self.i2c.i2c_sr2.msl().read_bit();
-        let future = self.i2c_ev.add_future(fib::new_fn(move || {
+        let release = self.err_release();
+        #[cfg(feature = "ack-timing")]
+        let (ack_clock, ack_timing) = (self.ack_clock, Arc::clone(&self.ack_timing));
+        self.i2c_ev.add_future(fib::new_fn(move || {
+            if release.err.is_set() {
+                // the error interrupt handler has already released the bus
+                return fib::Complete(());
+            }
             let sr1_val = i2c_sr1.load_val();
-            if i2c_sr1.sb().read(&sr1_val) {
-                // start condition generated
-                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr))); // 8-bit data register
-                fib::Yielded(())
-            } else if i2c_sr1.addr().read(&sr1_val) {
-                let sr2_val = i2c_sr2.load_val();
-                // end of address transmission
-                if i2c_sr2.tra().read(&sr2_val) {
-                    // transmitter
+            if i2c_sr1.af().read(&sr1_val) {
+                // the address or a data byte is not acknowledged
+                release.release(I2CError::Nack(0));
+                return fib::Complete(());
+            }
+            let addr_flag = i2c_sr1.addr().read(&sr1_val);
+            #[cfg(feature = "ack-timing")]
+            if addr_flag {
+                ack_timing.ack(ack_clock);
+            }
+            if hold && addr_flag && state == I2CEvState::Addr {
+                // end of address transmission, ADDR is left set to stretch SCL
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(());
+            }
+            let flags = I2CEvFlags {
+                sb: i2c_sr1.sb().read(&sr1_val),
+                addr: addr_flag,
+                btf: i2c_sr1.btf().read(&sr1_val),
+                add10: i2c_sr1.add10().read(&sr1_val),
+                // reading SR2 after SR1 clears ADDR
+                tra: addr_flag && i2c_sr2.tra().read_bit(),
+            };
+            let (action, next) = ev_transition(state, flags, addr);
+            state = next;
+            match action {
+                I2CEvAction::Wait => fib::Yielded(()),
+                I2CEvAction::WriteAddr => {
+                    // start condition generated
+                    i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr))); // 8-bit data register
                     fib::Yielded(())
-                } else {
-                    fib::Complete(())
                 }
-            } else if i2c_sr1.btf().read(&sr1_val) {
-                // data byte transfer succeeded
-                if repeated {
-                    set_start();
+                I2CEvAction::WriteHeader => {
+                    // start condition generated
+                    i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr & !1))); // 8-bit data register
                     fib::Yielded(())
-                } else {
+                }
+                I2CEvAction::WriteAddrLow => {
+                    // master has sent first address byte (header)
+                    i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(low))); // 8-bit data register
+                    fib::Yielded(())
+                }
+                I2CEvAction::Restart => {
+                    // end of address transmission
+                    i2c_cr1.start().set_bit(); // start generation
+                    fib::Yielded(())
+                }
+                I2CEvAction::Received => {
                     i2c_cr2.itevten().clear_bit(); // event interrupt disable
                     fib::Complete(())
                 }
-            } else {
-                fib::Yielded(())
+                I2CEvAction::Transmitted => {
+                    // data byte transfer succeeded
+                    i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                    if pending.as_ref().map_or(false, |pending| pending.load(Ordering::Acquire)) {
+                        // the next chunk is not armed yet, the interrupt is re-enabled by DMA
+                        fib::Yielded(())
+                    } else {
+                        fib::Complete(())
+                    }
+                }
+                I2CEvAction::DirectionMismatch => {
+                    // the R/W bit of the address doesn't match the operation
+                    release.release(I2CError::DirectionMismatch);
+                    fib::Complete(())
+                }
+            }
+        }))
+    }
+
+    fn slave_end(&mut self, tx: bool) -> impl Future<Output = ()> {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let i2c_sr1 = self.i2c.i2c_sr1;
+        let i2c_dr = self.i2c.i2c_dr;
+        let dma_tx_ccr = self.dma_tx.dma_ccr;
+        let dma_rx_ccr = self.dma_rx.dma_ccr;
+        let err = Arc::clone(&self.err);
+        let future = self.i2c_ev.add_future(fib::new_fn(move || {
+            if err.is_set() {
+                // the master doesn't acknowledge the last byte, or a bus error occurs
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(());
+            }
+            let sr1_val = i2c_sr1.load_val();
+            if i2c_sr1.stopf().read(&sr1_val) {
+                // stop condition detected
+                i2c_cr1.modify_reg(|_, _| {}); // clear STOPF by writing CR1 after reading SR1
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(());
             }
+            if i2c_sr1.addr().read(&sr1_val) {
+                // repeated start condition, ADDR is left set for the next request
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(());
+            }
+            if i2c_sr1.btf().read(&sr1_val) {
+                if tx && !dma_tx_ccr.en().read_bit() {
+                    // the master reads more bytes than the buffer holds
+                    i2c_dr.store_reg(|r, v| r.dr().write(v, 0xFF)); // 8-bit data register
+                } else if !tx && !dma_rx_ccr.en().read_bit() {
+                    // the master writes more bytes than the buffer holds
+                    i2c_dr.load_val(); // 8-bit data register
+                }
+            }
+            fib::Yielded(())
         }));
         self.i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
-        if !repeated {
-            set_start();
-        }
         future
     }
 
-    fn init_i2c(&mut self, i2c_freq: u32, i2c_presc: u32, i2c_trise: u32, i2c_mode: I2CMode) {
-        self.i2c.rcc_busenr_i2cen.set_bit(); // I2C clock enable
-        self.i2c.i2c_cr2.store_reg(|r, v| {
-            r.last().set(v); // next DMA EOT is the last transfer
-            r.dmaen().set(v); // DMA requests enable
-            r.iterren().set(v); // error interrupt enable
-            r.freq().write(v, i2c_freq); // peripheral clock frequency
-        });
-        self.i2c.i2c_ccr.store_reg(|r, v| {
-            match i2c_mode {
-                I2CMode::Sm1 => {
-                    r.f_s().clear(v); // Sm mode I2C
-                }
-                I2CMode::Fm2 => {
-                    r.f_s().set(v); // Fm mode I2C
-                    r.duty().clear(v); // Fm mode t_low/t_high = 2
-                }
-                I2CMode::Fm169 => {
-                    r.f_s().set(v); // Fm mode I2C
-                    r.duty().set(v); // Fm mode t_low/t_high = 16/9
+    /// Serves the data phase byte by byte from the event interrupt. `data`
+    /// services the data register, and returns `true` if a byte is
+    /// transferred.
+    fn slave_serve<F>(&mut self, mut data: F) -> impl Future<Output = usize>
+    where
+        F: FnMut(I2C::CI2CSr1, &I2C::I2CSr1Val, usize) -> bool + Send + 'static,
+    {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let i2c_sr1 = self.i2c.i2c_sr1;
+        let err = Arc::clone(&self.err);
+        let mut count = 0;
+        let future = self.i2c_ev.add_future(fib::new_fn(move || {
+            if err.is_set() {
+                // the master doesn't acknowledge the last byte, or a bus error occurs
+                i2c_cr2.modify_reg(|r, v| {
+                    r.itbufen().clear(v); // buffer interrupt disable
+                    r.itevten().clear(v); // event interrupt disable
+                });
+                return fib::Complete(count);
+            }
+            let sr1_val = i2c_sr1.load_val();
+            // the last byte is received before the Stop condition
+            if data(i2c_sr1, &sr1_val, count) {
+                count += 1;
+                return fib::Yielded(());
+            }
+            if i2c_sr1.stopf().read(&sr1_val) || i2c_sr1.addr().read(&sr1_val) {
+                if i2c_sr1.stopf().read(&sr1_val) {
+                    // stop condition detected
+                    i2c_cr1.modify_reg(|_, _| {}); // clear STOPF by writing CR1 after reading SR1
                 }
+                // on a repeated start condition, ADDR is left set for the next request
+                i2c_cr2.modify_reg(|r, v| {
+                    r.itbufen().clear(v); // buffer interrupt disable
+                    r.itevten().clear(v); // event interrupt disable
+                });
+                return fib::Complete(count);
             }
-            r.ccr().write(v, i2c_presc); // SCL clock in master mode
-        });
-        self.i2c.i2c_trise.store_reg(|r, v| {
-            r.trise().write(v, i2c_trise); // maximum rise time in Fm/Sm mode
+            fib::Yielded(())
+        }));
+        self.i2c.i2c_cr2.modify_reg(|r, v| {
+            r.itbufen().set(v); // buffer interrupt enable
+            r.itevten().set(v); // event interrupt enable
         });
-        self.i2c.i2c_cr1.store_reg(|r, v| r.pe().set(v)); // peripheral enable
+        future
+    }
+
+    fn init_i2c_err(&mut self) {
         let i2c_sr1 = self.i2c.i2c_sr1;
+        let i2c_sr2 = self.i2c.i2c_sr2;
+        let release = self.err_release();
+        let smbus_alert = Arc::clone(&self.smbus_alert);
+        let detached = Arc::clone(&self.detached);
         self.i2c_er.add_fn(move || {
+            if detached.load(Ordering::Acquire) {
+                return fib::Complete(());
+            }
             let val = i2c_sr1.load_val();
-            handle_i2c_err::<I2C>(&val, i2c_sr1);
-            fib::Yielded::<(), !>(())
+            if i2c_sr1.smbalert().read(&val) {
+                i2c_sr1.modify_reg(|r, v| r.smbalert().clear(v)); // clear SMBus alert
+                smbus_alert.store(true, Ordering::Release);
+            }
+            if let Some(error) = I2CError::load::<I2C>(&val, i2c_sr1) {
+                if matches!(error, I2CError::Nack(_)) && !i2c_sr2.msl().read_bit() {
+                    // the remote master ends a read from the slave
+                    release.end_slave_tx();
+                } else {
+                    release.release(error);
+                }
+                // complete the event futures
+                unsafe { I2CEv::wakeup_unchecked() };
+            }
+            fib::Yielded(())
         });
     }
 
-    fn init_dma_tx(&mut self, channel: u32, priority: u32) {
+    fn err_release(&self) -> ErrRelease<I2C, DmaTx, DmaRx> {
+        ErrRelease {
+            i2c_cr1: self.i2c.i2c_cr1,
+            i2c_cr2: self.i2c.i2c_cr2,
+            i2c_sr1: self.i2c.i2c_sr1,
+            dma_tx_ccr: self.dma_tx.dma_ccr,
+            dma_tx_cndtr: self.dma_tx.dma_cndtr,
+            dma_rx_ccr: self.dma_rx.dma_ccr,
+            err: Arc::clone(&self.err),
+            on_error: self.on_error,
+        }
+    }
+
+    fn init_dma_tx(&mut self, channel: u32, priority: u32, burst: u32) {
         let address = self.i2c.i2c_dr.as_mut_ptr(); // 8-bit data register
         self.dma_tx.dma_cpar.store_reg(|r, v| {
             r.pa().write(v, address as u32); // peripheral address
         });
+        self.dma_tx.dma_cfcr.store_reg(|r, v| {
+            if burst == 0 {
+                r.dmdis().clear(v); // direct mode enabled
+            } else {
+                r.dmdis().set(v); // direct mode disabled
+                r.fth().write(v, fifo_threshold(burst)); // FIFO threshold selection
+            }
+        });
         self.dma_tx.dma_ccr.store_reg(|r, v| {
             r.chsel().write(v, channel); // channel selection
+            r.mburst().write(v, burst); // memory burst transfer configuration
             r.pl().write(v, priority); // priority level
             r.msize().write(v, 0b00); // byte (8-bit)
             r.psize().write(v, 0b00); // byte (8-bit)
@@ -340,23 +3113,35 @@ impl<
             r.tcie().clear(v); // transfer complete interrupt disable
             r.teie().set(v); // transfer error interrupt enable
         });
-        let dma_isr_dmeif = self.dma_tx.dma_isr_dmeif;
-        let dma_isr_feif = self.dma_tx.dma_isr_feif;
         let dma_isr_teif = self.dma_tx.dma_isr_teif;
+        let dma_err = DmaErr::new(&self.dma_tx, self.on_error);
+        let detached = Arc::clone(&self.detached);
         self.dma_tx_int.add_fn(move || {
+            if detached.load(Ordering::Acquire) {
+                return fib::Complete(());
+            }
             let val = dma_isr_teif.load_val();
-            handle_dma_err::<DmaTx>(&val, dma_isr_dmeif, dma_isr_feif, dma_isr_teif);
-            fib::Yielded::<(), !>(())
+            dma_err.handle(&val);
+            fib::Yielded(())
         });
     }
 
-    fn init_dma_rx(&mut self, channel: u32, priority: u32) {
+    fn init_dma_rx(&mut self, channel: u32, priority: u32, burst: u32) {
         let address = self.i2c.i2c_dr.as_ptr(); // 8-bit data register
         self.dma_rx.dma_cpar.store_reg(|r, v| {
             r.pa().write(v, address as u32); // peripheral address
         });
+        self.dma_rx.dma_cfcr.store_reg(|r, v| {
+            if burst == 0 {
+                r.dmdis().clear(v); // direct mode enabled
+            } else {
+                r.dmdis().set(v); // direct mode disabled
+                r.fth().write(v, fifo_threshold(burst)); // FIFO threshold selection
+            }
+        });
         self.dma_rx.dma_ccr.store_reg(|r, v| {
             r.chsel().write(v, channel); // channel selection
+            r.mburst().write(v, burst); // memory burst transfer configuration
             r.pl().write(v, priority); // priority level
             r.msize().write(v, 0b00); // byte (8-bit)
             r.psize().write(v, 0b00); // byte (8-bit)
@@ -369,37 +3154,440 @@ impl<
     }
 }
 
-fn handle_dma_err<T: DmaChMap>(
-    val: &T::DmaIsrVal,
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn init_i2c<I2C: I2CMap>(
+    i2c: &mut I2CDiverged<I2C>,
+    i2c_freq: u32,
+    i2c_presc: u32,
+    i2c_trise: u32,
+    i2c_mode: I2CMode,
+    i2c_anfoff: bool,
+    i2c_dnf: u8,
+    i2c_smbus: I2CSmbusMode,
+    dma: bool,
+) {
+    i2c.rcc_busenr_i2cen.set_bit(); // I2C clock enable
+    setup_i2c(i2c, i2c_freq, i2c_presc, i2c_trise, i2c_mode, i2c_anfoff, i2c_dnf, i2c_smbus, dma);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn setup_i2c<I2C: I2CMap>(
+    i2c: &mut I2CDiverged<I2C>,
+    i2c_freq: u32,
+    i2c_presc: u32,
+    i2c_trise: u32,
+    i2c_mode: I2CMode,
+    i2c_anfoff: bool,
+    i2c_dnf: u8,
+    i2c_smbus: I2CSmbusMode,
+    dma: bool,
+) {
+    assert!(i2c_dnf <= 0xF, "Digital noise filter out of range");
+    // FLTR is writable only while the peripheral is disabled
+    i2c.i2c_cr1.store_reg(|r, v| r.pe().clear(v)); // peripheral disable
+    i2c.i2c_fltr.store_reg(|r, v| {
+        if i2c_anfoff {
+            r.anoff().set(v); // analog noise filter disable
+        } else {
+            r.anoff().clear(v); // analog noise filter enable
+        }
+        r.dnf().write(v, u32::from(i2c_dnf)); // digital noise filter
+    });
+    i2c.i2c_cr2.store_reg(|r, v| {
+        if dma {
+            r.last().set(v); // next DMA EOT is the last transfer
+            r.dmaen().set(v); // DMA requests enable
+        }
+        r.iterren().set(v); // error interrupt enable
+        r.freq().write(v, i2c_freq); // peripheral clock frequency
+    });
+    i2c.i2c_ccr.store_reg(|r, v| {
+        match i2c_mode {
+            I2CMode::Sm1 => {
+                r.f_s().clear(v); // Sm mode I2C
+            }
+            I2CMode::Fm2 => {
+                r.f_s().set(v); // Fm mode I2C
+                r.duty().clear(v); // Fm mode t_low/t_high = 2
+            }
+            I2CMode::Fm169 => {
+                r.f_s().set(v); // Fm mode I2C
+                r.duty().set(v); // Fm mode t_low/t_high = 16/9
+            }
+        }
+        r.ccr().write(v, i2c_presc); // SCL clock in master mode
+    });
+    i2c.i2c_trise.store_reg(|r, v| {
+        r.trise().write(v, i2c_trise); // maximum rise time in Fm/Sm mode
+    });
+    i2c.i2c_cr1.store_reg(|r, v| {
+        match i2c_smbus {
+            I2CSmbusMode::Disabled => {
+                r.smbus().clear(v); // I2C mode
+            }
+            I2CSmbusMode::Device => {
+                r.smbus().set(v); // SMBus mode
+                r.smbtype().clear(v); // SMBus Device
+            }
+            I2CSmbusMode::Host => {
+                r.smbus().set(v); // SMBus mode
+                r.smbtype().set(v); // SMBus Host
+            }
+        }
+        r.pe().set(v) // peripheral enable
+    });
+}
+
+const CCR_MAX: u32 = 0xFFF;
+const FREQ_MAX: u32 = 42;
+const DMA_CHUNK: usize = 0xFFF0; // the largest NDTR value aligned to any burst size
+
+/// Acknowledge setup of a transfer, which is applied together with the Start
+/// bit, so that it's in place before ADDR is cleared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Ack {
+    /// No byte is acknowledged. Used for writes and single-byte reads.
+    Off,
+    /// Every byte is acknowledged, until the DMA controller signals the end of
+    /// the last but one byte with the LAST bit set.
+    On,
+    /// The first byte is acknowledged, and the second one is not. Used for
+    /// two-byte reads, where the DMA end of transfer signal comes too late.
+    Next,
+}
+
+impl Ack {
+    /// Returns the acknowledge setup for a read of `len` bytes, following the
+    /// master receiver sequences of the reference manual.
+    pub(crate) fn rx(len: usize, last: bool) -> Self {
+        if !last || len > 2 {
+            Self::On
+        } else if len == 2 {
+            Self::Next
+        } else {
+            Self::Off
+        }
+    }
+
+    /// Returns `true` if the DMA chunk of a read, which is followed by `rest`
+    /// bytes, ends with the final byte of the transfer, which is not
+    /// acknowledged (CR2.LAST). An intermediate segment of a segmented read
+    /// acknowledges its last byte.
+    pub(crate) fn rx_last(rest: usize, last: bool) -> bool {
+        rest == 0 && last
+    }
+}
+
+/// DMA chunks of a read, each one as its length and the CR2.LAST bit.
+#[derive(Clone, Debug)]
+pub(crate) struct RxChunks {
+    rest: usize,
+    last: bool,
+}
+
+impl RxChunks {
+    /// Splits a read of `len` bytes, which is the final segment of the
+    /// transfer if `last` is `true`.
+    pub(crate) fn new(len: usize, last: bool) -> Self {
+        Self { rest: len, last }
+    }
+}
+
+impl Iterator for RxChunks {
+    type Item = (usize, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest == 0 {
+            return None;
+        }
+        let chunk = self.rest.min(DMA_CHUNK);
+        self.rest -= chunk;
+        Some((chunk, Ack::rx_last(self.rest, self.last)))
+    }
+}
+
+/// Returns the acknowledge setup applied with the Start signal, if the
+/// segment of `len` bytes is started with one, and the DMA chunks of the
+/// segment. Only the final segment of a segmented read doesn't acknowledge
+/// its last byte.
+pub(crate) fn rx_segment(len: usize, start: bool, last: bool) -> (Option<Ack>, RxChunks) {
+    let ack = if start { Some(Ack::rx(len, last)) } else { None };
+    (ack, RxChunks::new(len, last))
+}
+
+pub(crate) fn set_start<T: I2CMap>(i2c_cr1: T::CI2CCr1, ack: Ack) {
+    i2c_cr1.modify_reg(|r, v| {
+        match ack {
+            Ack::Off => {
+                r.ack().clear(v); // acknowledge disable
+                r.pos().clear(v); // ACK bit controls the current byte
+            }
+            Ack::On => {
+                r.ack().set(v); // acknowledge enable
+                r.pos().clear(v); // ACK bit controls the current byte
+            }
+            Ack::Next => {
+                r.ack().clear(v); // acknowledge disable
+                r.pos().set(v); // ACK bit controls the next byte
+            }
+        }
+        r.start().set(v); // start generation
+    });
+}
+
+fn fifo_threshold(burst: u32) -> u32 {
+    match burst {
+        0b01 => 0b00, // INCR4: 1/4 full FIFO
+        0b10 => 0b01, // INCR8: 1/2 full FIFO
+        0b11 => 0b11, // INCR16: full FIFO
+        _ => panic!("Invalid memory burst configuration"),
+    }
+}
+
+/// Returns `true` if the peripheral is the transmitter after the address
+/// phase, or [`I2CError::DirectionMismatch`] if it contradicts the R/W bit of
+/// `addr`.
+pub(crate) fn check_direction<T: I2CMap>(i2c_sr2: T::CI2CSr2, addr: u8) -> Result<bool, I2CError> {
+    let val = i2c_sr2.load_val(); // reading SR2 after SR1 clears ADDR
+    let transmitter = i2c_sr2.tra().read(&val);
+    if transmitter == (addr & 1 != 0) {
+        return Err(I2CError::DirectionMismatch);
+    }
+    Ok(transmitter)
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> fmt::Debug for I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("I2CDrv")
+            .field("config", &self.config)
+            .field("int_threshold", &self.int_threshold)
+            .finish()
+    }
+}
+
+struct ErrRelease<I2C: I2CMap, DmaTx: DmaChMap, DmaRx: DmaChMap> {
+    i2c_cr1: I2C::CI2CCr1,
+    i2c_cr2: I2C::CI2CCr2,
+    i2c_sr1: I2C::CI2CSr1,
+    dma_tx_ccr: DmaTx::CDmaCcr,
+    dma_tx_cndtr: DmaTx::CDmaCndtr,
+    dma_rx_ccr: DmaRx::CDmaCcr,
+    err: Arc<ErrorSlot>,
+    on_error: Option<fn(I2CErrorKind)>,
+}
+
+impl<I2C: I2CMap, DmaTx: DmaChMap, DmaRx: DmaChMap> ErrRelease<I2C, DmaTx, DmaRx> {
+    /// Clears the error flags, releases the bus, aborts the DMA transfers, and
+    /// passes `error` to the operation in progress. The number of acknowledged
+    /// bytes of [`I2CError::Nack`] is filled in from the transfer progress.
+    fn release(&self, error: I2CError) {
+        let mut sent = self.err.sent();
+        if self.i2c_cr2.dmaen().read_bit() {
+            // the bytes not yet taken by the DMA Tx stream
+            sent = sent.saturating_sub(self.dma_tx_cndtr.ndt().read_bits() as usize);
+        }
+        let error = error.with_sent(sent, self.i2c_sr1.tx_e().read_bit());
+        if let Some(on_error) = self.on_error {
+            on_error(error.into());
+        }
+        self.i2c_sr1.modify_reg(|r, v| {
+            r.berr().clear(v); // clear misplaced Start or Stop condition
+            r.arlo().clear(v); // clear arbitration lost
+            r.af().clear(v); // clear acknowledge failure
+            r.ovr().clear(v); // clear overrun/underrun
+            r.timeout().clear(v); // clear timeout or Tlow error
+            r.pecerr().clear(v); // clear PEC error in reception
+        });
+        self.i2c_cr1.modify_reg(|r, v| {
+            r.pos().clear(v); // ACK bit controls the current byte
+            if error != I2CError::ArbitrationLost {
+                // on arbitration loss the bus is released by the hardware
+                r.stop().set(v); // stop generation
+            }
+            r.enpec().clear(v); // PEC calculation disable
+        });
+        self.i2c_cr2.modify_reg(|r, v| {
+            r.itbufen().clear(v); // buffer interrupt disable
+            r.itevten().clear(v); // event interrupt disable
+        });
+        // disabling the streams sets TCIF, which completes the DMA futures
+        self.dma_tx_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+        self.dma_rx_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+        self.err.set(error);
+    }
+
+    /// Aborts the transfer of a dropped operation future without calling the
+    /// error hook, unless it's already aborted, and waits until the DMA Rx
+    /// stream stops writing to the buffer.
+    fn cancel<I2CEv: IntToken>(self, watchdog: fn()) {
+        let dma_rx_ccr = self.dma_rx_ccr;
+        if !self.err.is_set() {
+            ErrRelease { on_error: None, ..self }.release(I2CError::Timeout);
+            // complete the event futures
+            unsafe { I2CEv::wakeup_unchecked() };
+        }
+        while dma_rx_ccr.en().read_bit() {
+            // wait for the stream to stop
+            watchdog();
+        }
+        // a dropped stream read leaves the double buffer mode enabled
+        dma_rx_ccr.modify_reg(|r, v| r.dbm().clear(v)); // no buffer switching
+    }
+
+    /// Clears the acknowledge failure, which ends a slave transmission, and
+    /// aborts the DMA transfer.
+    fn end_slave_tx(&self) {
+        self.i2c_sr1.modify_reg(|r, v| r.af().clear(v)); // clear acknowledge failure
+        self.dma_tx_ccr.modify_reg(|r, v| r.en().clear(v)); // stream disable
+        self.err.set(I2CError::Nack(0));
+    }
+}
+
+/// The DMA Rx stream programmed by [`I2CDrv::prearm_rx`], but not yet enabled.
+pub(crate) struct RxPrearm<T: Future<Output = ()>> {
+    armed: Arc<AtomicBool>,
+    future: T,
+    len: usize,
+    rest_last: bool,
+}
+
+/// Cancels the transfer of an operation future, which is dropped before
+/// completion, see [`I2CDrv::cancel_guard`].
+pub(crate) struct CancelGuard<I2C: I2CMap, I2CEv: IntToken, DmaTx: DmaChMap, DmaRx: DmaChMap> {
+    release: Option<ErrRelease<I2C, DmaTx, DmaRx>>,
+    watchdog: fn(),
+    i2c_ev: PhantomData<I2CEv>,
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken, DmaTx: DmaChMap, DmaRx: DmaChMap>
+    CancelGuard<I2C, I2CEv, DmaTx, DmaRx>
+{
+    /// Aborts the transfer with `error`, the same way as the error interrupt
+    /// handler does, while the operation future is still in progress.
+    pub(crate) fn abort(&self, error: I2CError) {
+        if let Some(release) = &self.release {
+            release.release(error);
+            // complete the event futures
+            unsafe { I2CEv::wakeup_unchecked() };
+        }
+    }
+
+    /// Leaves the transfer alone, which is completed.
+    pub(crate) fn disarm(mut self) {
+        self.release = None;
+    }
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken, DmaTx: DmaChMap, DmaRx: DmaChMap> Drop
+    for CancelGuard<I2C, I2CEv, DmaTx, DmaRx>
+{
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release.cancel::<I2CEv>(self.watchdog);
+        }
+    }
+}
+
+struct DmaErr<T: DmaChMap> {
+    dma_ifcr_cdmeif: T::CDmaIfcrCdmeif,
+    dma_ifcr_cfeif: T::CDmaIfcrCfeif,
+    dma_ifcr_cteif: T::CDmaIfcrCteif,
     dma_isr_dmeif: T::CDmaIsrDmeif,
     dma_isr_feif: T::CDmaIsrFeif,
     dma_isr_teif: T::CDmaIsrTeif,
-) {
-    if dma_isr_teif.read(&val) {
-        panic!("Transfer error");
+    on_error: Option<fn(I2CErrorKind)>,
+}
+
+impl<T: DmaChMap> DmaErr<T> {
+    fn new(dma: &DmaChDiverged<T>, on_error: Option<fn(I2CErrorKind)>) -> Self {
+        Self {
+            dma_ifcr_cdmeif: dma.dma_ifcr_cdmeif,
+            dma_ifcr_cfeif: dma.dma_ifcr_cfeif,
+            dma_ifcr_cteif: dma.dma_ifcr_cteif,
+            dma_isr_dmeif: dma.dma_isr_dmeif,
+            dma_isr_feif: dma.dma_isr_feif,
+            dma_isr_teif: dma.dma_isr_teif,
+            on_error,
+        }
     }
-    if dma_isr_dmeif.read(&val) {
-        panic!("Direct mode error");
+
+    fn handle(&self, val: &T::DmaIsrVal) {
+        if self.dma_isr_teif.read(val) {
+            self.dma_ifcr_cteif.set_bit(); // clear transfer error interrupt flag
+            self.report(I2CErrorKind::TransferError);
+        }
+        if self.dma_isr_dmeif.read(val) {
+            self.dma_ifcr_cdmeif.set_bit(); // clear direct mode error interrupt flag
+            self.report(I2CErrorKind::DirectModeError);
+        }
+        if self.dma_isr_feif.read(val) {
+            self.dma_ifcr_cfeif.set_bit(); // clear FIFO error interrupt flag
+            self.report(I2CErrorKind::FifoError);
+        }
     }
-    if dma_isr_feif.read(&val) {
-        panic!("FIFO error");
+
+    fn report(&self, kind: I2CErrorKind) {
+        match self.on_error {
+            Some(on_error) => on_error(kind),
+            None => panic!("{}", kind),
+        }
     }
 }
 
-fn handle_i2c_err<T: I2CMap>(val: &T::I2CSr1Val, i2c_sr1: T::CI2CSr1) {
-    if i2c_sr1.berr().read(&val) {
-        panic!("Misplaced Start or Stop condition");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(len: usize, start: bool, last: bool) -> (Option<Ack>, Vec<(usize, bool)>) {
+        let (ack, chunks) = rx_segment(len, start, last);
+        (ack, chunks.collect())
     }
-    if i2c_sr1.arlo().read(&val) {
-        panic!("Arbitration Lost detected");
+
+    #[test]
+    fn single_read() {
+        assert_eq!(segment(1, true, true), (Some(Ack::Off), vec![(1, true)]));
+        assert_eq!(segment(2, true, true), (Some(Ack::Next), vec![(2, true)]));
+        assert_eq!(segment(3, true, true), (Some(Ack::On), vec![(3, true)]));
+        assert_eq!(
+            segment(DMA_CHUNK + 1, true, true),
+            (Some(Ack::On), vec![(DMA_CHUNK, false), (1, true)])
+        );
     }
-    if i2c_sr1.af().read(&val) {
-        panic!("Acknowledge failure");
+
+    #[test]
+    fn two_segment_read() {
+        // the intermediate segment acknowledges its last byte
+        assert_eq!(segment(4, true, false), (Some(Ack::On), vec![(4, false)]));
+        // only the final segment doesn't acknowledge its last byte
+        assert_eq!(segment(4, false, true), (None, vec![(4, true)]));
     }
-    if i2c_sr1.ovr().read(&val) {
-        panic!("Overrun or underrun");
+
+    #[test]
+    fn two_segment_read_short_segments() {
+        // a short intermediate segment doesn't disable the acknowledge
+        assert_eq!(segment(1, true, false), (Some(Ack::On), vec![(1, false)]));
+        assert_eq!(segment(2, true, false), (Some(Ack::On), vec![(2, false)]));
+        assert_eq!(segment(1, false, true), (None, vec![(1, true)]));
     }
-    if i2c_sr1.timeout().read(&val) {
-        panic!("SCL remained LOW for 25 ms");
+
+    #[test]
+    fn two_segment_read_long_segments() {
+        assert_eq!(
+            segment(DMA_CHUNK + 1, true, false),
+            (Some(Ack::On), vec![(DMA_CHUNK, false), (1, false)])
+        );
+        assert_eq!(
+            segment(DMA_CHUNK + 1, false, true),
+            (None, vec![(DMA_CHUNK, false), (1, true)])
+        );
     }
 }
@@ -0,0 +1,37 @@
+/// Bus operation recorded in the dry-run mode.
+///
+/// See [`I2CDrv::set_dry_run`](crate::I2CDrv::set_dry_run).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum I2COp {
+    /// Write of `data` to the slave at the address `addr`.
+    Write {
+        /// Slave address.
+        addr: u8,
+        /// Data to be written.
+        data: Vec<u8>,
+    },
+    /// Read of `len` bytes from the slave at the address `addr`.
+    Read {
+        /// Slave address.
+        addr: u8,
+        /// Number of bytes to be read.
+        len: usize,
+    },
+    /// Stop signal.
+    Stop,
+}
+
+pub(crate) fn record(ops: &mut Option<Vec<I2COp>>, op: I2COp) -> bool {
+    if let Some(ops) = ops {
+        match op {
+            I2COp::Write { addr, .. } | I2COp::Read { addr, .. } => {
+                assert!(addr <= 0x7F, "Slave address out of 7-bit range");
+            }
+            I2COp::Stop => {}
+        }
+        ops.push(op);
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,288 @@
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+use drone_cortexm::reg::prelude::*;
+use drone_stm32_map::periph::i2c::{traits::*, I2CMap};
+
+/// I²C bus error.
+///
+/// Returned by the [`I2CMaster`](crate::I2CMaster) operations. When an error
+/// is detected, the driver clears the error flags, and releases the bus, so
+/// that a new session can be started.
+///
+/// [`I2CError::BusError`], [`I2CError::ArbitrationLost`], and
+/// [`I2CError::Overrun`] are glitches of the bus itself, and are recoverable
+/// mid-session: the failed operation can be restarted as is, see
+/// [`I2CMaster::set_retries`](crate::I2CMaster::set_retries). The other errors
+/// are fatal for the operation, as restarting it can't change the outcome, or
+/// can repeat a side effect on the slave.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CError {
+    /// Misplaced Start or Stop condition (SR1.BERR).
+    BusError,
+    /// Arbitration lost to another master (SR1.ARLO).
+    ArbitrationLost,
+    /// The address or a data byte is not acknowledged (SR1.AF).
+    ///
+    /// Holds the number of data bytes acknowledged by the slave before the
+    /// failure, e.g. `Nack(3)` if the slave accepted 3 bytes, and didn't
+    /// acknowledge the 4th one. It's zero if the address is not acknowledged.
+    Nack(usize),
+    /// Overrun or underrun (SR1.OVR).
+    Overrun,
+    /// SCL remained low for 25 ms (SR1.TIMEOUT), or the operation timeout
+    /// elapsed, see [`I2CMaster::set_timeout`](crate::I2CMaster::set_timeout).
+    Timeout,
+    /// The received PEC byte doesn't match the calculated one (SR1.PECERR).
+    Pec,
+    /// The transfer direction reported by the hardware after the address
+    /// phase (SR2.TRA) doesn't match the requested operation. This indicates
+    /// a wrong R/W bit of the address, e.g. an addressing bug.
+    DirectionMismatch,
+    /// The slave didn't acknowledge a number of consecutive operations, and is
+    /// considered absent, see
+    /// [`I2CDrv::set_nack_threshold`](crate::I2CDrv::set_nack_threshold).
+    DeviceGone,
+    /// The bus is not idle before a transfer, see
+    /// [`I2CDrv::assert_bus_idle`](crate::I2CDrv::assert_bus_idle).
+    BusBusy,
+}
+
+impl I2CError {
+    pub(crate) fn load<T: I2CMap>(val: &T::I2CSr1Val, i2c_sr1: T::CI2CSr1) -> Option<Self> {
+        if i2c_sr1.berr().read(val) {
+            Some(Self::BusError)
+        } else if i2c_sr1.arlo().read(val) {
+            Some(Self::ArbitrationLost)
+        } else if i2c_sr1.af().read(val) {
+            Some(Self::Nack(0))
+        } else if i2c_sr1.ovr().read(val) {
+            Some(Self::Overrun)
+        } else if i2c_sr1.timeout().read(val) {
+            Some(Self::Timeout)
+        } else if i2c_sr1.pecerr().read(val) {
+            Some(Self::Pec)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the error is a bus glitch, after which the failed
+    /// operation can be restarted, see [`I2CError`].
+    #[must_use]
+    pub fn is_recoverable(self) -> bool {
+        matches!(self, Self::BusError | Self::ArbitrationLost | Self::Overrun)
+    }
+
+    /// Fills in the number of acknowledged bytes of [`I2CError::Nack`], given
+    /// the number of bytes `sent` to the data register, and the SR1.TXE flag.
+    pub(crate) fn with_sent(self, sent: usize, tx_e: bool) -> Self {
+        match self {
+            // the not acknowledged byte, and the next byte if it's already
+            // loaded into the data register
+            Self::Nack(_) => Self::Nack(sent.saturating_sub(if tx_e { 1 } else { 2 })),
+            error => error,
+        }
+    }
+
+    fn from_code(code: u8, acked: usize) -> Option<Self> {
+        match code {
+            1 => Some(Self::BusError),
+            2 => Some(Self::ArbitrationLost),
+            3 => Some(Self::Nack(acked)),
+            4 => Some(Self::Overrun),
+            5 => Some(Self::Timeout),
+            6 => Some(Self::Pec),
+            7 => Some(Self::DirectionMismatch),
+            8 => Some(Self::DeviceGone),
+            9 => Some(Self::BusBusy),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Self::BusError => 1,
+            Self::ArbitrationLost => 2,
+            Self::Nack(_) => 3,
+            Self::Overrun => 4,
+            Self::Timeout => 5,
+            Self::Pec => 6,
+            Self::DirectionMismatch => 7,
+            Self::DeviceGone => 8,
+            Self::BusBusy => 9,
+        }
+    }
+}
+
+impl fmt::Display for I2CError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BusError => write!(f, "Misplaced Start or Stop condition"),
+            Self::ArbitrationLost => write!(f, "Arbitration Lost detected"),
+            Self::Nack(acked) => write!(f, "Acknowledge failure after {} bytes", acked),
+            Self::Overrun => write!(f, "Overrun or underrun"),
+            Self::Timeout => write!(f, "Transfer timed out"),
+            Self::Pec => write!(f, "PEC error in reception"),
+            Self::DirectionMismatch => write!(f, "Transfer direction mismatch"),
+            Self::DeviceGone => write!(f, "Device is gone"),
+            Self::BusBusy => write!(f, "Bus is busy"),
+        }
+    }
+}
+
+/// I²C configuration error.
+///
+/// Returned by [`I2CDrv::init`](crate::I2CDrv::init) and
+/// [`I2CConfig::validate`](crate::I2CConfig::validate) when a setup value is
+/// out of the range allowed by the reference manual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CConfigError {
+    /// The peripheral clock frequency is out of range 2 to 42 MHz, or below 4
+    /// MHz in Fast-mode.
+    Freq,
+    /// The clock prescaler is out of range 4 to 4095 in Standard-mode, or 1 to
+    /// 4095 in Fast-mode.
+    Presc,
+    /// The maximum rise time is zero, or exceeds the peripheral clock
+    /// frequency in MHz plus one.
+    Trise,
+    /// The digital noise filter is out of range 0 to 15.
+    Dnf,
+    /// SCL or SDA line is not pulled high, see
+    /// [`I2CDrv::check_pullups`](crate::I2CDrv::check_pullups).
+    MissingPullups,
+    /// The peripheral is in master mode (SR2.MSL), e.g. a session was leaked
+    /// without generating a Stop signal.
+    BusBusy,
+}
+
+impl fmt::Display for I2CConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Freq => write!(f, "Peripheral clock frequency out of range"),
+            Self::Presc => write!(f, "Clock prescaler out of range"),
+            Self::Trise => write!(f, "Maximum rise time out of range"),
+            Self::Dnf => write!(f, "Digital noise filter out of range"),
+            Self::MissingPullups => write!(f, "Missing pull-up resistors"),
+            Self::BusBusy => write!(f, "Bus is busy"),
+        }
+    }
+}
+
+/// Kind of an error passed to the error hook.
+///
+/// See [`I2CSetup::on_error`](crate::I2CSetup::on_error).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CErrorKind {
+    /// DMA transfer error (DMA_xISR.TEIFx).
+    TransferError,
+    /// DMA FIFO error (DMA_xISR.FEIFx).
+    FifoError,
+    /// DMA direct mode error (DMA_xISR.DMEIFx).
+    DirectModeError,
+    /// See [`I2CError::BusError`].
+    BusError,
+    /// See [`I2CError::ArbitrationLost`].
+    ArbitrationLost,
+    /// See [`I2CError::Nack`].
+    Nack,
+    /// See [`I2CError::Overrun`].
+    Overrun,
+    /// See [`I2CError::Timeout`].
+    Timeout,
+    /// See [`I2CError::Pec`].
+    Pec,
+    /// See [`I2CError::DirectionMismatch`].
+    DirectionMismatch,
+    /// See [`I2CError::BusBusy`]. It's returned by the pre-flight check, and
+    /// is never passed to the error hook.
+    BusBusy,
+}
+
+impl From<I2CError> for I2CErrorKind {
+    fn from(error: I2CError) -> Self {
+        match error {
+            I2CError::BusError => Self::BusError,
+            I2CError::ArbitrationLost => Self::ArbitrationLost,
+            // a device gone is reported from the consecutive acknowledge failures
+            I2CError::Nack(_) | I2CError::DeviceGone => Self::Nack,
+            I2CError::Overrun => Self::Overrun,
+            I2CError::Timeout => Self::Timeout,
+            I2CError::Pec => Self::Pec,
+            I2CError::DirectionMismatch => Self::DirectionMismatch,
+            I2CError::BusBusy => Self::BusBusy,
+        }
+    }
+}
+
+impl fmt::Display for I2CErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TransferError => write!(f, "Transfer error"),
+            Self::FifoError => write!(f, "FIFO error"),
+            Self::DirectModeError => write!(f, "Direct mode error"),
+            Self::BusError => I2CError::BusError.fmt(f),
+            Self::ArbitrationLost => I2CError::ArbitrationLost.fmt(f),
+            Self::Nack => write!(f, "Acknowledge failure"),
+            Self::Overrun => I2CError::Overrun.fmt(f),
+            Self::Timeout => I2CError::Timeout.fmt(f),
+            Self::Pec => I2CError::Pec.fmt(f),
+            Self::DirectionMismatch => I2CError::DirectionMismatch.fmt(f),
+            Self::BusBusy => I2CError::BusBusy.fmt(f),
+        }
+    }
+}
+
+/// Error passed from the error interrupt handler to the operation in
+/// progress.
+pub(crate) struct ErrorSlot {
+    code: AtomicU8,
+    acked: AtomicUsize,
+    sent: AtomicUsize,
+}
+
+impl ErrorSlot {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            code: AtomicU8::new(0),
+            acked: AtomicUsize::new(0),
+            sent: AtomicUsize::new(0),
+        })
+    }
+
+    /// Stores `error`, unless an earlier error is not taken yet.
+    pub(crate) fn set(&self, error: I2CError) {
+        if self.code.compare_exchange(0, error.code(), Ordering::AcqRel, Ordering::Acquire).is_ok()
+        {
+            if let I2CError::Nack(acked) = error {
+                self.acked.store(acked, Ordering::Release);
+            }
+        }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.code.load(Ordering::Acquire) != 0
+    }
+
+    /// Returns and clears the stored error, and resets the transfer progress
+    /// for the next operation.
+    pub(crate) fn take(&self) -> Option<I2CError> {
+        self.sent.store(0, Ordering::Release);
+        I2CError::from_code(self.code.swap(0, Ordering::AcqRel), self.acked.load(Ordering::Acquire))
+    }
+
+    /// Accounts `len` bytes written to the data register by an interrupt
+    /// handler, or loaded into the DMA Tx stream.
+    pub(crate) fn add_sent(&self, len: usize) {
+        self.sent.fetch_add(len, Ordering::AcqRel);
+    }
+
+    /// Returns the number of bytes accounted by [`ErrorSlot::add_sent`] since
+    /// the last [`ErrorSlot::take`].
+    pub(crate) fn sent(&self) -> usize {
+        self.sent.load(Ordering::Acquire)
+    }
+}
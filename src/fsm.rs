@@ -0,0 +1,460 @@
+/// Event handler state of a DMA master operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CEvState {
+    /// Waiting for the Start condition.
+    Start,
+    /// Waiting for the end of the address transmission.
+    Addr,
+    /// Waiting for the end of the last data byte transmission.
+    Data,
+    /// Waiting for the Start condition of a 10-bit addressed operation.
+    Start10,
+    /// Waiting for the end of the 10-bit header transmission.
+    Header10,
+    /// Waiting for the end of the 10-bit address transmission of a read, which
+    /// is followed by a repeated Start.
+    Addr10Read,
+    /// Waiting for the repeated Start condition of a 10-bit addressed read.
+    Restart10,
+}
+
+/// Status flags sampled by the event handler.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct I2CEvFlags {
+    /// SR1.SB flag.
+    pub sb: bool,
+    /// SR1.ADDR flag.
+    pub addr: bool,
+    /// SR1.BTF flag.
+    pub btf: bool,
+    /// SR1.ADD10 flag.
+    pub add10: bool,
+    /// SR2.TRA flag. Sampled only if [`addr`](I2CEvFlags::addr) is set.
+    pub tra: bool,
+}
+
+/// Action to be taken by the event handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CEvAction {
+    /// Nothing to do, wait for the next event.
+    Wait,
+    /// Write the address byte to the DR register.
+    WriteAddr,
+    /// Write the 10-bit header with the direction bit cleared to the DR
+    /// register.
+    WriteHeader,
+    /// Write the second byte of the 10-bit address to the DR register.
+    WriteAddrLow,
+    /// Generate a repeated Start condition.
+    Restart,
+    /// The slave is addressed for reception, which continues through DMA.
+    /// Complete the operation.
+    Received,
+    /// The last data byte is transmitted. Disable the event interrupt and
+    /// complete the operation.
+    Transmitted,
+    /// The transfer direction reported by the peripheral doesn't match the
+    /// address byte.
+    DirectionMismatch,
+}
+
+/// Transition function of the event handler state machine.
+///
+/// Takes the current `state`, the sampled status `flags`, and the address byte
+/// `addr` with the direction bit. For 10-bit addresses, `addr` is the header
+/// byte with the direction bit, and the initial state is
+/// [`I2CEvState::Start10`]. Returns the action to take and the next
+/// state. The function doesn't touch the hardware, so that it can be tested or
+/// fuzzed on the host.
+#[must_use]
+pub fn ev_transition(state: I2CEvState, flags: I2CEvFlags, addr: u8) -> (I2CEvAction, I2CEvState) {
+    match state {
+        I2CEvState::Start if flags.sb => (I2CEvAction::WriteAddr, I2CEvState::Addr),
+        I2CEvState::Addr if flags.addr => {
+            if flags.tra == (addr & 1 != 0) {
+                (I2CEvAction::DirectionMismatch, state)
+            } else if flags.tra {
+                (I2CEvAction::Wait, I2CEvState::Data)
+            } else {
+                (I2CEvAction::Received, state)
+            }
+        }
+        I2CEvState::Data if flags.btf => (I2CEvAction::Transmitted, state),
+        I2CEvState::Start10 if flags.sb => (I2CEvAction::WriteHeader, I2CEvState::Header10),
+        I2CEvState::Header10 if flags.add10 => {
+            let next = if addr & 1 == 0 { I2CEvState::Addr } else { I2CEvState::Addr10Read };
+            (I2CEvAction::WriteAddrLow, next)
+        }
+        I2CEvState::Addr10Read if flags.addr => {
+            if flags.tra {
+                (I2CEvAction::Restart, I2CEvState::Restart10)
+            } else {
+                (I2CEvAction::DirectionMismatch, state)
+            }
+        }
+        I2CEvState::Restart10 if flags.sb => (I2CEvAction::WriteAddr, I2CEvState::Addr),
+        _ => (I2CEvAction::Wait, state),
+    }
+}
+
+/// Start signal tracking of a master session.
+///
+/// The session tracks whether it holds the bus on its own, instead of reading
+/// SR2.MSL, which can be stale after an error. The next operation begins with
+/// a repeated Start only if the previous one left the bus held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct I2CSessionStart {
+    started: bool,
+    repeated: bool,
+}
+
+impl I2CSessionStart {
+    /// Returns `true` if the session holds the bus.
+    pub(crate) fn is_started(self) -> bool {
+        self.started
+    }
+
+    /// Returns `true` if the current operation begins with a repeated Start.
+    pub(crate) fn is_repeated(self) -> bool {
+        self.repeated
+    }
+
+    /// Begins an operation, which holds the bus afterwards.
+    pub(crate) fn begin(&mut self) {
+        self.repeated = self.started;
+        self.started = true;
+    }
+
+    /// Restarts the current operation with a fresh Start, after the bus is
+    /// released by a recoverable error.
+    pub(crate) fn restart(&mut self) {
+        self.repeated = false;
+    }
+
+    /// Accounts the bus released by the error interrupt handler.
+    pub(crate) fn released(&mut self) {
+        self.started = false;
+    }
+
+    /// Ends the session hold of the bus. Returns `true` if the Stop signal
+    /// should be sent.
+    pub(crate) fn stop(&mut self) -> bool {
+        core::mem::replace(&mut self.started, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRITE: u8 = 0x50 << 1;
+    const READ: u8 = 0x50 << 1 | 1;
+    const HEADER_WRITE: u8 = 0b1111_0110;
+    const HEADER_READ: u8 = 0b1111_0111;
+
+    fn sb() -> I2CEvFlags {
+        I2CEvFlags { sb: true, ..I2CEvFlags::default() }
+    }
+
+    fn addr(tra: bool) -> I2CEvFlags {
+        I2CEvFlags { addr: true, tra, ..I2CEvFlags::default() }
+    }
+
+    fn btf() -> I2CEvFlags {
+        I2CEvFlags { btf: true, ..I2CEvFlags::default() }
+    }
+
+    fn add10() -> I2CEvFlags {
+        I2CEvFlags { add10: true, ..I2CEvFlags::default() }
+    }
+
+    #[test]
+    fn write_7bit() {
+        let (action, state) = ev_transition(I2CEvState::Start, sb(), WRITE);
+        assert_eq!((action, state), (I2CEvAction::WriteAddr, I2CEvState::Addr));
+        let (action, state) = ev_transition(state, addr(true), WRITE);
+        assert_eq!((action, state), (I2CEvAction::Wait, I2CEvState::Data));
+        let (action, state) = ev_transition(state, btf(), WRITE);
+        assert_eq!((action, state), (I2CEvAction::Transmitted, I2CEvState::Data));
+    }
+
+    #[test]
+    fn read_7bit() {
+        let (action, state) = ev_transition(I2CEvState::Start, sb(), READ);
+        assert_eq!((action, state), (I2CEvAction::WriteAddr, I2CEvState::Addr));
+        let (action, state) = ev_transition(state, addr(false), READ);
+        assert_eq!((action, state), (I2CEvAction::Received, I2CEvState::Addr));
+    }
+
+    #[test]
+    fn direction_mismatch() {
+        assert_eq!(
+            ev_transition(I2CEvState::Addr, addr(false), WRITE),
+            (I2CEvAction::DirectionMismatch, I2CEvState::Addr)
+        );
+        assert_eq!(
+            ev_transition(I2CEvState::Addr, addr(true), READ),
+            (I2CEvAction::DirectionMismatch, I2CEvState::Addr)
+        );
+        assert_eq!(
+            ev_transition(I2CEvState::Addr10Read, addr(false), HEADER_READ),
+            (I2CEvAction::DirectionMismatch, I2CEvState::Addr10Read)
+        );
+    }
+
+    #[test]
+    fn write_10bit() {
+        let (action, state) = ev_transition(I2CEvState::Start10, sb(), HEADER_WRITE);
+        assert_eq!((action, state), (I2CEvAction::WriteHeader, I2CEvState::Header10));
+        let (action, state) = ev_transition(state, add10(), HEADER_WRITE);
+        assert_eq!((action, state), (I2CEvAction::WriteAddrLow, I2CEvState::Addr));
+        let (action, state) = ev_transition(state, addr(true), HEADER_WRITE);
+        assert_eq!((action, state), (I2CEvAction::Wait, I2CEvState::Data));
+        let (action, _) = ev_transition(state, btf(), HEADER_WRITE);
+        assert_eq!(action, I2CEvAction::Transmitted);
+    }
+
+    #[test]
+    fn read_10bit() {
+        let (action, state) = ev_transition(I2CEvState::Start10, sb(), HEADER_READ);
+        assert_eq!((action, state), (I2CEvAction::WriteHeader, I2CEvState::Header10));
+        let (action, state) = ev_transition(state, add10(), HEADER_READ);
+        assert_eq!((action, state), (I2CEvAction::WriteAddrLow, I2CEvState::Addr10Read));
+        let (action, state) = ev_transition(state, addr(true), HEADER_READ);
+        assert_eq!((action, state), (I2CEvAction::Restart, I2CEvState::Restart10));
+        let (action, state) = ev_transition(state, sb(), HEADER_READ);
+        assert_eq!((action, state), (I2CEvAction::WriteAddr, I2CEvState::Addr));
+        let (action, _) = ev_transition(state, addr(false), HEADER_READ);
+        assert_eq!(action, I2CEvAction::Received);
+    }
+
+    #[test]
+    fn waits_for_expected_flag() {
+        let cases = [
+            (I2CEvState::Start, addr(true)),
+            (I2CEvState::Start, btf()),
+            (I2CEvState::Start, add10()),
+            (I2CEvState::Addr, sb()),
+            (I2CEvState::Addr, btf()),
+            (I2CEvState::Data, sb()),
+            (I2CEvState::Data, addr(true)),
+            (I2CEvState::Start10, add10()),
+            (I2CEvState::Header10, sb()),
+            (I2CEvState::Header10, addr(true)),
+            (I2CEvState::Addr10Read, add10()),
+            (I2CEvState::Restart10, addr(true)),
+        ];
+        for &(state, flags) in &cases {
+            assert_eq!(ev_transition(state, flags, WRITE), (I2CEvAction::Wait, state));
+        }
+    }
+
+    #[test]
+    fn no_flags_wait() {
+        let states = [
+            I2CEvState::Start,
+            I2CEvState::Addr,
+            I2CEvState::Data,
+            I2CEvState::Start10,
+            I2CEvState::Header10,
+            I2CEvState::Addr10Read,
+            I2CEvState::Restart10,
+        ];
+        for &state in &states {
+            assert_eq!(
+                ev_transition(state, I2CEvFlags::default(), READ),
+                (I2CEvAction::Wait, state)
+            );
+        }
+    }
+
+    #[test]
+    fn leftover_flags_ignored() {
+        // a stale BTF from the previous operation doesn't complete the next one
+        let flags = I2CEvFlags { sb: true, btf: true, ..I2CEvFlags::default() };
+        assert_eq!(
+            ev_transition(I2CEvState::Start, flags, WRITE),
+            (I2CEvAction::WriteAddr, I2CEvState::Addr)
+        );
+        // a stale SB after the address is written doesn't restart the operation
+        let flags = I2CEvFlags { sb: true, addr: true, tra: true, ..I2CEvFlags::default() };
+        assert_eq!(
+            ev_transition(I2CEvState::Addr, flags, WRITE),
+            (I2CEvAction::Wait, I2CEvState::Data)
+        );
+    }
+
+    #[test]
+    fn session_start() {
+        let mut start = I2CSessionStart::default();
+        assert!(!start.is_started());
+        start.begin();
+        assert!(start.is_started());
+        assert!(!start.is_repeated());
+        assert!(start.stop());
+        assert!(!start.stop());
+    }
+
+    #[test]
+    fn session_repeated_start() {
+        let mut start = I2CSessionStart::default();
+        start.begin();
+        start.begin();
+        assert!(start.is_repeated());
+        start.begin();
+        assert!(start.is_repeated());
+        assert!(start.stop());
+        start.begin();
+        assert!(!start.is_repeated());
+    }
+
+    #[test]
+    fn session_error_then_start() {
+        let mut start = I2CSessionStart::default();
+        start.begin();
+        start.begin();
+        start.released();
+        assert!(!start.is_started());
+        // the bus is released by the error, no Stop signal is sent
+        assert!(!start.stop());
+        start.begin();
+        assert!(!start.is_repeated());
+        start.begin();
+        assert!(start.is_repeated());
+    }
+
+    #[test]
+    fn session_restart_after_recoverable_error() {
+        let mut start = I2CSessionStart::default();
+        start.begin();
+        start.begin();
+        start.restart();
+        assert!(!start.is_repeated());
+        assert!(start.is_started());
+        start.begin();
+        assert!(start.is_repeated());
+    }
+
+    #[cfg(feature = "fuzzing")]
+    mod fuzz {
+        use super::*;
+
+        const ITERATIONS: usize = 100_000;
+
+        /// Xorshift pseudo-random generator, so that the harness doesn't need
+        /// extra dependencies and is reproducible.
+        struct Rng(u32);
+
+        impl Rng {
+            fn next(&mut self) -> u32 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 17;
+                self.0 ^= self.0 << 5;
+                self.0
+            }
+
+            fn flags(&mut self) -> I2CEvFlags {
+                let bits = self.next();
+                I2CEvFlags {
+                    sb: bits & 1 != 0,
+                    addr: bits & 2 != 0,
+                    btf: bits & 4 != 0,
+                    add10: bits & 8 != 0,
+                    tra: bits & 16 != 0,
+                }
+            }
+        }
+
+        /// Flag, which the action of a transition is allowed to react to.
+        fn trigger(state: I2CEvState, flags: I2CEvFlags) -> bool {
+            match state {
+                I2CEvState::Start | I2CEvState::Start10 | I2CEvState::Restart10 => flags.sb,
+                I2CEvState::Addr | I2CEvState::Addr10Read => flags.addr,
+                I2CEvState::Data => flags.btf,
+                I2CEvState::Header10 => flags.add10,
+            }
+        }
+
+        #[test]
+        fn random_flags() {
+            let mut rng = Rng(0x2545_f491);
+            for _ in 0..ITERATIONS {
+                let addr = rng.next() as u8;
+                let mut state =
+                    if rng.next() & 1 == 0 { I2CEvState::Start } else { I2CEvState::Start10 };
+                for _ in 0..16 {
+                    let flags = rng.flags();
+                    let (action, next) = ev_transition(state, flags, addr);
+                    if action == I2CEvAction::Wait {
+                        // waiting moves only past the address of a write
+                        assert!(
+                            next == state || (state, next) == (I2CEvState::Addr, I2CEvState::Data)
+                        );
+                    } else {
+                        assert!(
+                            trigger(state, flags),
+                            "{:?} in {:?} on {:?}",
+                            action,
+                            state,
+                            flags
+                        );
+                    }
+                    match action {
+                        I2CEvAction::Received
+                        | I2CEvAction::Transmitted
+                        | I2CEvAction::DirectionMismatch => break,
+                        _ => state = next,
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn conforming_peripheral_completes() {
+            let mut rng = Rng(0x1234_5678);
+            for _ in 0..ITERATIONS {
+                let addr = rng.next() as u8;
+                let ten_bit = rng.next() & 1 != 0;
+                let read = addr & 1 != 0;
+                let mut state = if ten_bit { I2CEvState::Start10 } else { I2CEvState::Start };
+                let mut restarted = false;
+                let mut done = false;
+                for _ in 0..256 {
+                    // the expected flag is raised at random events
+                    let mut flags = I2CEvFlags::default();
+                    let expected = rng.next() & 1 != 0;
+                    match state {
+                        I2CEvState::Start | I2CEvState::Start10 | I2CEvState::Restart10 => {
+                            flags.sb = expected;
+                        }
+                        I2CEvState::Header10 => flags.add10 = expected,
+                        I2CEvState::Addr10Read => {
+                            flags.addr = expected;
+                            flags.tra = true;
+                        }
+                        I2CEvState::Addr => {
+                            flags.addr = expected;
+                            // after the 10-bit read restart the peripheral
+                            // reports the receiver mode
+                            flags.tra = !read || (ten_bit && !restarted);
+                        }
+                        I2CEvState::Data => flags.btf = expected,
+                    }
+                    let (action, next) = ev_transition(state, flags, addr);
+                    match action {
+                        I2CEvAction::Received | I2CEvAction::Transmitted => {
+                            done = true;
+                            break;
+                        }
+                        I2CEvAction::DirectionMismatch => panic!("{:?} on {:?}", state, flags),
+                        I2CEvAction::Restart => restarted = true,
+                        _ => {}
+                    }
+                    state = next;
+                }
+                assert!(done, "{:?} is not completed", state);
+            }
+        }
+    }
+}
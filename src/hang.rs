@@ -0,0 +1,124 @@
+use crate::diverged::{DmaChDiverged, I2CDiverged};
+use core::fmt;
+use drone_cortexm::reg::prelude::*;
+use drone_stm32_map::periph::{
+    dma::ch::{traits::*, DmaChMap},
+    i2c::{traits::*, I2CMap},
+};
+
+/// Assessment of the driver state for a transfer, which doesn't complete.
+///
+/// Returned by [`I2CDrv::diagnose_hang`](crate::I2CDrv::diagnose_hang) for
+/// diagnostic purposes. The [`Display`](fmt::Display) implementation gives a
+/// human-readable description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2CHangDiagnosis {
+    /// No transfer in progress.
+    Idle,
+    /// The bus is busy, but the peripheral is not the master.
+    BusBusy,
+    /// CR1.START is set, but SR1.SB is not.
+    StartPending,
+    /// CR1.STOP is set, but the Stop condition is not generated yet.
+    StopPending,
+    /// SR1.SB is set, but the event interrupt is disabled.
+    EventIntDisabled,
+    /// SR1.SB is set, and the event interrupt is not serviced.
+    SbNotHandled,
+    /// SR1.ADDR is set, and not cleared.
+    AddrNotCleared,
+    /// A DMA stream is enabled, and the transfer doesn't progress.
+    DmaInProgress {
+        /// Number of data items left in the stream.
+        remaining: u32,
+    },
+    /// SR1.BTF is set, and the event interrupt is not serviced.
+    BtfNotHandled,
+    /// The last operation is finished, and the bus is held for the next one.
+    SessionHeld,
+    /// The event interrupt is enabled, and no events are pending.
+    AwaitingEvent,
+}
+
+impl I2CHangDiagnosis {
+    pub(crate) fn load<I2C: I2CMap, DmaTx: DmaChMap, DmaRx: DmaChMap>(
+        i2c: &I2CDiverged<I2C>,
+        dma_tx: &DmaChDiverged<DmaTx>,
+        dma_rx: &DmaChDiverged<DmaRx>,
+    ) -> Self {
+        // SR2 is read before SR1, because reading SR2 after SR1 clears ADDR
+        let sr2_val = i2c.i2c_sr2.load_val();
+        let sr1_val = i2c.i2c_sr1.load_val();
+        let cr1_val = i2c.i2c_cr1.load_val();
+        let itevten = i2c.i2c_cr2.itevten().read_bit();
+        if i2c.i2c_cr1.stop().read(&cr1_val) {
+            return Self::StopPending;
+        }
+        if !i2c.i2c_sr2.msl().read(&sr2_val) {
+            return if i2c.i2c_cr1.start().read(&cr1_val) {
+                Self::StartPending
+            } else if i2c.i2c_sr2.busy().read(&sr2_val) {
+                Self::BusBusy
+            } else {
+                Self::Idle
+            };
+        }
+        if i2c.i2c_sr1.sb().read(&sr1_val) {
+            return if itevten { Self::SbNotHandled } else { Self::EventIntDisabled };
+        }
+        if i2c.i2c_sr1.addr().read(&sr1_val) {
+            return Self::AddrNotCleared;
+        }
+        if dma_tx.dma_ccr.en().read_bit() {
+            return Self::DmaInProgress { remaining: dma_tx.dma_cndtr.ndt().read_bits() };
+        }
+        if dma_rx.dma_ccr.en().read_bit() {
+            return Self::DmaInProgress { remaining: dma_rx.dma_cndtr.ndt().read_bits() };
+        }
+        if !itevten {
+            Self::SessionHeld
+        } else if i2c.i2c_sr1.btf().read(&sr1_val) {
+            Self::BtfNotHandled
+        } else {
+            Self::AwaitingEvent
+        }
+    }
+}
+
+impl fmt::Display for I2CHangDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Idle => write!(f, "no transfer in progress"),
+            Self::BusBusy => write!(
+                f,
+                "bus busy, but not the master: SDA or SCL is held low, or another master is active"
+            ),
+            Self::StartPending => {
+                write!(f, "waiting for SB: Start is never generated, bus stuck?")
+            }
+            Self::StopPending => {
+                write!(f, "waiting for Stop generation: SCL is held low by a slave?")
+            }
+            Self::EventIntDisabled => write!(f, "SB set, but the event interrupt is disabled"),
+            Self::SbNotHandled => {
+                write!(f, "SB set, but the address is not written: event interrupt not serviced?")
+            }
+            Self::AddrNotCleared => write!(f, "ADDR set but not cleared"),
+            Self::DmaInProgress { remaining } => write!(
+                f,
+                "DMA transfer with {} items left: the slave is stretching SCL, or the address is \
+                 not transmitted yet",
+                remaining
+            ),
+            Self::BtfNotHandled => write!(f, "BTF set, but the event interrupt is not serviced"),
+            Self::SessionHeld => {
+                write!(f, "operation finished, the session holds the bus without a Stop")
+            }
+            Self::AwaitingEvent => write!(
+                f,
+                "waiting for an event: the address or the last byte is being transmitted, or the \
+                 slave is stretching SCL"
+            ),
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{
+    blocking,
+    diverged::I2CDiverged,
+    drv::{check_direction, init_i2c, set_start, Ack},
+    error::ErrorSlot,
+    I2CError, I2CIntMaster, I2CMode, I2CSmbusMode,
+};
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use drone_cortexm::{fib, reg::prelude::*, thr::prelude::*};
+use drone_stm32_map::periph::i2c::{traits::*, I2CMap, I2CPeriph};
+use futures::prelude::*;
+
+/// I²C setup for the interrupt-only driver.
+pub struct I2CIntSetup<I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> {
+    /// I²C peripheral.
+    pub i2c: I2CPeriph<I2C>,
+    /// I²C event interrupt.
+    pub i2c_ev: I2CEv,
+    /// I²C error interrupt.
+    pub i2c_er: I2CEr,
+    /// I²C peripheral clock frequency.
+    ///
+    /// See [`I2CSetup::i2c_freq`](crate::I2CSetup::i2c_freq).
+    pub i2c_freq: u32,
+    /// I²C clock prescaler.
+    ///
+    /// See [`I2CSetup::i2c_presc`](crate::I2CSetup::i2c_presc).
+    pub i2c_presc: u32,
+    /// I²C maximum rise time.
+    ///
+    /// See [`I2CSetup::i2c_trise`](crate::I2CSetup::i2c_trise).
+    pub i2c_trise: u32,
+    /// I²C bus mode.
+    ///
+    /// See [`I2CSetup::i2c_mode`](crate::I2CSetup::i2c_mode).
+    pub i2c_mode: I2CMode,
+}
+
+/// I²C driver without DMA.
+///
+/// Unlike [`I2CDrv`](crate::I2CDrv), this driver doesn't require any DMA
+/// channels. Every data byte is transferred by the I²C event interrupt
+/// handler, which costs an interrupt per byte. Prefer [`I2CDrv`](crate::I2CDrv)
+/// when DMA streams are available.
+pub struct I2CIntDrv<I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> {
+    i2c: I2CDiverged<I2C>,
+    i2c_ev: I2CEv,
+    i2c_er: I2CEr,
+    watchdog: fn(),
+    err: Arc<ErrorSlot>,
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Start,
+    Addr,
+    Data,
+    Done,
+}
+
+#[derive(Clone, Copy)]
+enum RegPhase {
+    Start,
+    Addr,
+    Reg,
+    Restart,
+    ReadAddr,
+    Data,
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> I2CIntDrv<I2C, I2CEv, I2CEr> {
+    /// Sets up a new [`I2CIntDrv`] from `setup` values.
+    #[must_use]
+    pub fn init(setup: I2CIntSetup<I2C, I2CEv, I2CEr>) -> Self {
+        let I2CIntSetup { i2c, i2c_ev, i2c_er, i2c_freq, i2c_presc, i2c_trise, i2c_mode } = setup;
+        let mut drv =
+            Self { i2c: i2c.into(), i2c_ev, i2c_er, watchdog: || {}, err: ErrorSlot::new() };
+        init_i2c(
+            &mut drv.i2c,
+            i2c_freq,
+            i2c_presc,
+            i2c_trise,
+            i2c_mode,
+            false,
+            0,
+            I2CSmbusMode::Disabled,
+            false,
+        );
+        drv.init_i2c_err();
+        drv
+    }
+
+    /// Creates a new master session.
+    ///
+    /// This method can block if previous Stop signal generation is not
+    /// finished. The watchdog hook is called while blocking.
+    ///
+    /// The returned session object takes ownership of `buf`, which can be later
+    /// returned by [`I2CIntMaster::stop`] method. If the `stop` method is not
+    /// called, the Stop signal is sent and `buf` is freed.
+    #[inline]
+    pub fn master(&mut self, buf: Box<[u8]>) -> I2CIntMaster<'_, I2C, I2CEv, I2CEr> {
+        while self.i2c.i2c_cr1.stop().read_bit() {
+            // stop generation
+            (self.watchdog)();
+        }
+        // discard an error left from an earlier session
+        self.err.take();
+        I2CIntMaster::new(self, buf)
+    }
+
+    /// Writes `buf_tx` to the slave at the address `addr`, and sends the Stop
+    /// signal, by polling the status flags.
+    ///
+    /// This transfer path doesn't use the interrupts nor the DMA, so it works
+    /// before the interrupt controller is set up, and from a panic handler with
+    /// the interrupts disabled. The I²C interrupts and the DMA requests are
+    /// disabled for the transfer, and restored after it. The calling thread is
+    /// blocked for the whole transfer, and the watchdog hook is called while
+    /// waiting. Use it for bring-up and diagnostics only.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released.
+    pub fn write_blocking(&mut self, addr: u8, buf_tx: &[u8]) -> Result<(), I2CError> {
+        blocking::write_blocking(&self.i2c, self.watchdog, addr, buf_tx)
+    }
+
+    /// Reads from the slave at the address `addr` into `buf_rx`, and sends the
+    /// Stop signal, by polling the status flags.
+    ///
+    /// See [`I2CIntDrv::write_blocking`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released.
+    ///
+    /// # Panics
+    ///
+    /// If `buf_rx` is empty.
+    pub fn read_blocking(&mut self, addr: u8, buf_rx: &mut [u8]) -> Result<(), I2CError> {
+        blocking::read_blocking(&self.i2c, self.watchdog, addr, buf_rx)
+    }
+
+    /// Sets the watchdog hook, which is called from the busy-wait loop of
+    /// [`I2CIntDrv::master`].
+    ///
+    /// See [`I2CDrv::set_watchdog_hook`](crate::I2CDrv::set_watchdog_hook) for
+    /// details.
+    pub fn set_watchdog_hook(&mut self, hook: fn()) {
+        self.watchdog = hook;
+    }
+
+    pub(crate) unsafe fn write(&mut self, addr: u8, buf_tx: &[u8]) -> impl Future<Output = ()> {
+        write_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, buf_tx)
+    }
+
+    pub(crate) unsafe fn read(&mut self, addr: u8, buf_rx: &mut [u8]) -> impl Future<Output = ()> {
+        read_int(&self.i2c, self.i2c_ev, Arc::clone(&self.err), addr, buf_rx)
+    }
+
+    /// Returns and clears the error reported by the error interrupt handler
+    /// since the last call.
+    pub(crate) fn take_error(&mut self) -> Option<I2CError> {
+        self.err.take()
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.i2c.i2c_cr1.stop().set_bit(); // stop generation
+    }
+
+    /// Aborts the transfer of an operation future dropped or leaked before
+    /// completion, unless it's already aborted. The fibers of the transfer
+    /// are completed with a timeout error.
+    pub(crate) fn cancel(&self) {
+        self.cancel_guard().cancel();
+    }
+
+    /// Returns a guard, which cancels the transfer with [`I2CIntDrv::cancel`]
+    /// when dropped before [`IntCancelGuard::disarm`], i.e. when the operation
+    /// future is dropped before completion.
+    pub(crate) fn cancel_guard(&self) -> IntCancelGuard<I2C, I2CEv> {
+        IntCancelGuard {
+            i2c_cr1: self.i2c.i2c_cr1,
+            i2c_cr2: self.i2c.i2c_cr2,
+            err: Some(Arc::clone(&self.err)),
+            i2c_ev: PhantomData,
+        }
+    }
+
+    fn init_i2c_err(&mut self) {
+        let i2c_cr1 = self.i2c.i2c_cr1;
+        let i2c_cr2 = self.i2c.i2c_cr2;
+        let i2c_sr1 = self.i2c.i2c_sr1;
+        let err = Arc::clone(&self.err);
+        self.i2c_er.add_fn(move || {
+            let val = i2c_sr1.load_val();
+            if let Some(error) = I2CError::load::<I2C>(&val, i2c_sr1) {
+                let error = error.with_sent(err.sent(), i2c_sr1.tx_e().read(&val));
+                i2c_sr1.modify_reg(|r, v| {
+                    r.berr().clear(v); // clear misplaced Start or Stop condition
+                    r.arlo().clear(v); // clear arbitration lost
+                    r.af().clear(v); // clear acknowledge failure
+                    r.ovr().clear(v); // clear overrun/underrun
+                    r.timeout().clear(v); // clear timeout or Tlow error
+                    r.pecerr().clear(v); // clear PEC error in reception
+                });
+                if error != I2CError::ArbitrationLost {
+                    // on arbitration loss the bus is released by the hardware
+                    i2c_cr1.stop().set_bit(); // stop generation
+                }
+                i2c_cr2.modify_reg(|r, v| {
+                    r.itbufen().clear(v); // buffer interrupt disable
+                    r.itevten().clear(v); // event interrupt disable
+                });
+                err.set(error);
+                // complete the event futures
+                unsafe { I2CEv::wakeup_unchecked() };
+            }
+            fib::Yielded::<(), !>(())
+        });
+    }
+}
+
+pub(crate) unsafe fn write_int<I2C: I2CMap, I2CEv: IntToken>(
+    i2c: &I2CDiverged<I2C>,
+    i2c_ev: I2CEv,
+    err: Arc<ErrorSlot>,
+    addr: u8,
+    buf_tx: &[u8],
+) -> impl Future<Output = ()> {
+    let i2c_cr1 = i2c.i2c_cr1;
+    let i2c_cr2 = i2c.i2c_cr2;
+    let i2c_sr1 = i2c.i2c_sr1;
+    let i2c_sr2 = i2c.i2c_sr2;
+    let i2c_dr = i2c.i2c_dr;
+    let buf_ptr = buf_tx.as_ptr() as usize;
+    let buf_len = buf_tx.len();
+    let mut phase = Phase::Start;
+    let mut idx = 0;
+    let future = i2c_ev.add_future(fib::new_fn(move || {
+        if err.is_set() {
+            // the error interrupt handler has already released the bus
+            return fib::Complete(());
+        }
+        let sr1_val = i2c_sr1.load_val();
+        match phase {
+            Phase::Start if i2c_sr1.sb().read(&sr1_val) => {
+                // start condition generated
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1))); // 8-bit data register
+                phase = Phase::Addr;
+            }
+            Phase::Addr if i2c_sr1.addr().read(&sr1_val) => {
+                // end of address transmission
+                if let Err(error) = check_direction::<I2C>(i2c_sr2, addr << 1) {
+                    release::<I2C>(i2c_cr1, i2c_cr2, &err, error);
+                    return fib::Complete(());
+                }
+                if buf_len == 0 {
+                    i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                    return fib::Complete(());
+                }
+                i2c_cr2.itbufen().set_bit(); // buffer interrupt enable
+                phase = Phase::Data;
+            }
+            Phase::Data if i2c_sr1.tx_e().read(&sr1_val) => {
+                // data register empty
+                let byte = *(buf_ptr as *const u8).add(idx);
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(byte))); // 8-bit data register
+                err.add_sent(1);
+                idx += 1;
+                if idx == buf_len {
+                    i2c_cr2.itbufen().clear_bit(); // buffer interrupt disable
+                    phase = Phase::Done;
+                }
+            }
+            Phase::Done if i2c_sr1.btf().read(&sr1_val) => {
+                // data byte transfer succeeded
+                i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                return fib::Complete(());
+            }
+            _ => {}
+        }
+        fib::Yielded(())
+    }));
+    set_start::<I2C>(i2c.i2c_cr1, Ack::Off);
+    i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+    future
+}
+
+pub(crate) unsafe fn read_int<I2C: I2CMap, I2CEv: IntToken>(
+    i2c: &I2CDiverged<I2C>,
+    i2c_ev: I2CEv,
+    err: Arc<ErrorSlot>,
+    addr: u8,
+    buf_rx: &mut [u8],
+) -> impl Future<Output = ()> {
+    let i2c_cr1 = i2c.i2c_cr1;
+    let i2c_cr2 = i2c.i2c_cr2;
+    let i2c_sr1 = i2c.i2c_sr1;
+    let i2c_sr2 = i2c.i2c_sr2;
+    let i2c_dr = i2c.i2c_dr;
+    let buf_ptr = buf_rx.as_mut_ptr() as usize;
+    let buf_len = buf_rx.len();
+    let mut phase = Phase::Start;
+    let mut idx = 0;
+    let read_byte = move |idx: usize| {
+        let byte = i2c_dr.dr().read_bits() as u8; // 8-bit data register
+        *(buf_ptr as *mut u8).add(idx) = byte;
+    };
+    let future = i2c_ev.add_future(fib::new_fn(move || {
+        if err.is_set() {
+            // the error interrupt handler has already released the bus
+            return fib::Complete(());
+        }
+        let sr1_val = i2c_sr1.load_val();
+        match phase {
+            Phase::Start if i2c_sr1.sb().read(&sr1_val) => {
+                // start condition generated
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1 | 1))); // 8-bit data register
+                phase = Phase::Addr;
+            }
+            Phase::Addr if i2c_sr1.addr().read(&sr1_val) => {
+                // end of address transmission
+                match buf_len {
+                    0 | 1 => {
+                        i2c_cr1.ack().clear_bit(); // acknowledge disable
+                    }
+                    2 => {
+                        i2c_cr1.modify_reg(|r, v| {
+                            r.ack().clear(v); // acknowledge disable
+                            r.pos().set(v); // ACK bit controls the next byte
+                        });
+                    }
+                    _ => {}
+                }
+                if let Err(error) = check_direction::<I2C>(i2c_sr2, addr << 1 | 1) {
+                    release::<I2C>(i2c_cr1, i2c_cr2, &err, error);
+                    return fib::Complete(());
+                }
+                match buf_len {
+                    0 => {
+                        i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                        return fib::Complete(());
+                    }
+                    // the last bytes are received with BTF
+                    2 | 3 => {}
+                    _ => {
+                        i2c_cr2.itbufen().set_bit(); // buffer interrupt enable
+                    }
+                }
+                phase = Phase::Data;
+            }
+            Phase::Data => match buf_len - idx {
+                remaining @ (1 | 4..=usize::MAX) if i2c_sr1.rx_ne().read(&sr1_val) => {
+                    // data register not empty
+                    read_byte(idx);
+                    idx += 1;
+                    if remaining == 1 {
+                        i2c_cr2.modify_reg(|r, v| {
+                            r.itbufen().clear(v); // buffer interrupt disable
+                            r.itevten().clear(v); // event interrupt disable
+                        });
+                        return fib::Complete(());
+                    } else if remaining == 4 {
+                        i2c_cr2.itbufen().clear_bit(); // buffer interrupt disable
+                    }
+                }
+                3 if i2c_sr1.btf().read(&sr1_val) => {
+                    // data byte N-2 in DR, N-1 in the shift register
+                    i2c_cr1.ack().clear_bit(); // acknowledge disable
+                    read_byte(idx);
+                    read_byte(idx + 1);
+                    idx += 2;
+                    i2c_cr2.itbufen().set_bit(); // buffer interrupt enable
+                }
+                2 if i2c_sr1.btf().read(&sr1_val) => {
+                    // data byte N-1 in DR, N in the shift register
+                    read_byte(idx);
+                    read_byte(idx + 1);
+                    i2c_cr1.pos().clear_bit(); // ACK bit controls the current byte
+                    i2c_cr2.itevten().clear_bit(); // event interrupt disable
+                    return fib::Complete(());
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        fib::Yielded(())
+    }));
+    set_start::<I2C>(i2c.i2c_cr1, if buf_len > 1 { Ack::On } else { Ack::Off });
+    i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+    future
+}
+
+pub(crate) fn read_reg_int<I2C: I2CMap, I2CEv: IntToken>(
+    i2c: &I2CDiverged<I2C>,
+    i2c_ev: I2CEv,
+    err: Arc<ErrorSlot>,
+    addr: u8,
+    reg: u8,
+) -> impl Future<Output = u8> {
+    let i2c_cr1 = i2c.i2c_cr1;
+    let i2c_cr2 = i2c.i2c_cr2;
+    let i2c_sr1 = i2c.i2c_sr1;
+    let i2c_sr2 = i2c.i2c_sr2;
+    let i2c_dr = i2c.i2c_dr;
+    let mut phase = RegPhase::Start;
+    let future = i2c_ev.add_future(fib::new_fn(move || {
+        if err.is_set() {
+            // the error interrupt handler has already released the bus
+            return fib::Complete(0);
+        }
+        let sr1_val = i2c_sr1.load_val();
+        match phase {
+            RegPhase::Start if i2c_sr1.sb().read(&sr1_val) => {
+                // start condition generated
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1))); // 8-bit data register
+                phase = RegPhase::Addr;
+            }
+            RegPhase::Addr if i2c_sr1.addr().read(&sr1_val) => {
+                // end of address transmission
+                if let Err(error) = check_direction::<I2C>(i2c_sr2, addr << 1) {
+                    release::<I2C>(i2c_cr1, i2c_cr2, &err, error);
+                    return fib::Complete(0);
+                }
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(reg))); // 8-bit data register
+                phase = RegPhase::Reg;
+            }
+            RegPhase::Reg if i2c_sr1.btf().read(&sr1_val) => {
+                // data byte transfer succeeded
+                set_start::<I2C>(i2c_cr1, Ack::Off);
+                phase = RegPhase::Restart;
+            }
+            RegPhase::Restart if i2c_sr1.sb().read(&sr1_val) => {
+                // start condition generated
+                i2c_dr.store_reg(|r, v| r.dr().write(v, u32::from(addr << 1 | 1))); // 8-bit data register
+                phase = RegPhase::ReadAddr;
+            }
+            RegPhase::ReadAddr if i2c_sr1.addr().read(&sr1_val) => {
+                // end of address transmission
+                if let Err(error) = check_direction::<I2C>(i2c_sr2, addr << 1 | 1) {
+                    release::<I2C>(i2c_cr1, i2c_cr2, &err, error);
+                    return fib::Complete(0);
+                }
+                i2c_cr1.stop().set_bit(); // stop generation
+                i2c_cr2.itbufen().set_bit(); // buffer interrupt enable
+                phase = RegPhase::Data;
+            }
+            RegPhase::Data if i2c_sr1.rx_ne().read(&sr1_val) => {
+                // data register not empty
+                let byte = i2c_dr.dr().read_bits() as u8; // 8-bit data register
+                i2c_cr2.modify_reg(|r, v| {
+                    r.itbufen().clear(v); // buffer interrupt disable
+                    r.itevten().clear(v); // event interrupt disable
+                });
+                return fib::Complete(byte);
+            }
+            _ => {}
+        }
+        fib::Yielded(())
+    }));
+    set_start::<I2C>(i2c.i2c_cr1, Ack::Off);
+    i2c.i2c_cr2.itevten().set_bit(); // event interrupt enable
+    future
+}
+
+/// Cancels the transfer of an operation future, which is dropped before
+/// completion, see [`I2CIntDrv::cancel_guard`].
+pub(crate) struct IntCancelGuard<I2C: I2CMap, I2CEv: IntToken> {
+    i2c_cr1: I2C::CI2CCr1,
+    i2c_cr2: I2C::CI2CCr2,
+    err: Option<Arc<ErrorSlot>>,
+    i2c_ev: PhantomData<I2CEv>,
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken> IntCancelGuard<I2C, I2CEv> {
+    /// Leaves the transfer alone, which is completed.
+    pub(crate) fn disarm(mut self) {
+        self.err = None;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(err) = self.err.take() {
+            if !err.is_set() {
+                release::<I2C>(self.i2c_cr1, self.i2c_cr2, &err, I2CError::Timeout);
+                // complete the event futures
+                unsafe { I2CEv::wakeup_unchecked() };
+            }
+        }
+    }
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken> Drop for IntCancelGuard<I2C, I2CEv> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Releases the bus, and passes `error` to the operation in progress, e.g.
+/// after the address phase revealed a wrong transfer direction.
+fn release<I2C: I2CMap>(
+    i2c_cr1: I2C::CI2CCr1,
+    i2c_cr2: I2C::CI2CCr2,
+    err: &ErrorSlot,
+    error: I2CError,
+) {
+    i2c_cr1.modify_reg(|r, v| {
+        r.pos().clear(v); // ACK bit controls the current byte
+        r.stop().set(v); // stop generation
+    });
+    i2c_cr2.modify_reg(|r, v| {
+        r.itbufen().clear(v); // buffer interrupt disable
+        r.itevten().clear(v); // event interrupt disable
+    });
+    err.set(error);
+}
@@ -0,0 +1,145 @@
+use crate::{I2CError, I2CIntDrv};
+use core::{cell::Cell, mem::ManuallyDrop, slice::SliceIndex};
+use drone_cortexm::thr::prelude::*;
+use drone_stm32_map::periph::i2c::I2CMap;
+
+/// I²C master session of the interrupt-only driver.
+///
+/// The session object takes ownership of the provided buffer, which is returned
+/// by [`I2CIntMaster::stop`] method. If the session is dropped without calling
+/// the `stop` method, the Stop signal is sent and the buffer is freed.
+///
+/// If an operation fails with an [`I2CError`], the error interrupt handler
+/// clears the error flags, releases the bus, and completes the operation right
+/// away. The session stays valid: the buffer keeps the data transferred before
+/// the error, and can be taken back with [`I2CIntMaster::stop`]. If an
+/// operation future is dropped before completion, its transfer is aborted the
+/// same way, see [`I2CMaster`](crate::I2CMaster).
+pub struct I2CIntMaster<'a, I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> {
+    drv: &'a mut I2CIntDrv<I2C, I2CEv, I2CEr>,
+    buf: ManuallyDrop<Box<[u8]>>,
+    in_flight: Cell<bool>,
+    started: bool,
+}
+
+impl<'a, I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> I2CIntMaster<'a, I2C, I2CEv, I2CEr> {
+    pub(crate) fn new(drv: &'a mut I2CIntDrv<I2C, I2CEv, I2CEr>, buf: Box<[u8]>) -> Self {
+        Self { drv, buf: ManuallyDrop::new(buf), in_flight: Cell::new(false), started: false }
+    }
+
+    /// Sends the Start signal for the address `addr`, and writes the data from
+    /// the session buffer slice of the range `index` to the slave.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CIntMaster`].
+    pub async fn write<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        self.begin();
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        unsafe { self.drv.write(addr, &self.buf[index]).await };
+        guard.disarm();
+        self.in_flight.set(false);
+        self.check_error()
+    }
+
+    /// Sends the Start signal for the address `addr`, and reads the data from
+    /// the slave into the session buffer slice of the range `index`.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CIntMaster`].
+    pub async fn read<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        self.begin();
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        unsafe { self.drv.read(addr, &mut self.buf[index]).await };
+        guard.disarm();
+        self.in_flight.set(false);
+        self.check_error()
+    }
+
+    /// Returns a reference to the session buffer.
+    #[inline]
+    #[must_use]
+    pub fn buf(&self) -> &[u8] {
+        self.abort_leaked();
+        &self.buf
+    }
+
+    /// Returns a mutable reference to the session buffer.
+    #[inline]
+    #[must_use]
+    pub fn buf_mut(&mut self) -> &mut Box<[u8]> {
+        self.abort_leaked();
+        &mut self.buf
+    }
+
+    /// Sends the Stop signal and returns the session buffer.
+    ///
+    /// The Stop signal is sent only if the session holds the bus, i.e. it's
+    /// not sent after a failed operation, which has already released the bus.
+    #[must_use]
+    pub fn stop(mut self) -> Box<[u8]> {
+        self.settle();
+        self.release_bus();
+        let mut session = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut session.buf) }
+    }
+
+    fn begin(&mut self) {
+        self.settle();
+        self.started = true;
+    }
+
+    /// Aborts the transfer of an operation future leaked before completion.
+    fn abort_leaked(&self) {
+        if self.in_flight.replace(false) {
+            self.drv.cancel();
+        }
+    }
+
+    /// Accounts an error reported after the last operation, e.g. by an aborted
+    /// transfer of a dropped operation future. The bus is already released in
+    /// this case.
+    fn settle(&mut self) {
+        self.abort_leaked();
+        if self.drv.take_error().is_some() {
+            self.started = false;
+        }
+    }
+
+    fn release_bus(&mut self) {
+        if self.started {
+            self.drv.stop();
+            self.started = false;
+        }
+    }
+
+    fn check_error(&mut self) -> Result<(), I2CError> {
+        match self.drv.take_error() {
+            Some(err) => {
+                // the bus is already released by the error interrupt handler
+                self.started = false;
+                Err(err)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<I2C: I2CMap, I2CEv: IntToken, I2CEr: IntToken> Drop for I2CIntMaster<'_, I2C, I2CEv, I2CEr> {
+    fn drop(&mut self) {
+        self.settle();
+        self.release_bus();
+        unsafe { ManuallyDrop::drop(&mut self.buf) };
+    }
+}
@@ -2,12 +2,18 @@
 //!
 //! # Limitations
 //!
-//! * Transmission and reception works only through DMA channels with
-//! interrupts. Polling and interrupt only methods are not supported.
+//! * Transmission and reception works either through DMA channels with
+//! interrupts ([`I2CDrv`]), or through interrupts only ([`I2CIntDrv`]) for
+//! configurations without free DMA streams. Polling is supported only for
+//! blocking master transfers ([`I2CDrv::write_blocking`] and
+//! [`I2CDrv::read_blocking`]), which are meant for bring-up and diagnostics.
 //!
-//! * Errors from peripherals are handled via panicking.
+//! * Bus errors are returned from the [`I2CMaster`] and [`I2CIntMaster`]
+//! operations as [`I2CError`]. DMA errors are handled via panicking, unless an
+//! error hook is set with [`I2CSetup::on_error`].
 //!
-//! * Only the master role is implemented.
+//! * The slave role ([`I2CSlave`]) supports 7-bit own addresses only, and is
+//! not available in the interrupt-only driver.
 //!
 //! # Usage
 //!
@@ -73,7 +79,7 @@
 //!     gpio::periph_gpio_b,
 //!     i2c::periph_i2c1,
 //! };
-//! use smartoris_i2c::{I2CDrv, I2CMode, I2CSetup};
+//! use smartoris_i2c::{I2CDrv, I2CMode, I2CSetup, I2CSmbusMode};
 //!
 //! fn handler(reg: Regs, thr_init: ThrsInit) {
 //!     let thr = thr::init(thr_init);
@@ -116,19 +122,26 @@
 //!         i2c: periph_i2c1!(reg),
 //!         i2c_ev: thr.i2c1_ev,
 //!         i2c_er: thr.i2c1_er,
-//!         i2c_freq: 42,           // APB1 clock = 42 MHz
-//!         i2c_presc: 35,          // SCL clock = 400 kHz
-//!         i2c_trise: 13,          // 285.7 ns
-//!         i2c_mode: I2CMode::Fm2, // Fm mode t_low/t_high = 2
+//!         i2c_freq: 42,                      // APB1 clock = 42 MHz
+//!         i2c_presc: 35,                     // SCL clock = 400 kHz
+//!         i2c_trise: 13,                     // 285.7 ns
+//!         i2c_mode: I2CMode::Fm2,            // Fm mode t_low/t_high = 2
+//!         i2c_anfoff: false,                 // analog noise filter enable
+//!         i2c_dnf: 0,                        // digital noise filter disable
+//!         i2c_smbus: I2CSmbusMode::Disabled, // I2C mode
 //!         dma_tx: periph_dma1_ch6!(reg),
 //!         dma_tx_int: thr.dma1_ch6,
-//!         dma_tx_ch: 1,    // I2C1_TX
-//!         dma_tx_pl: 0b11, // very high
+//!         dma_tx_ch: 1,        // I2C1_TX
+//!         dma_tx_pl: 0b11,     // very high
+//!         dma_tx_mburst: 0b00, // single transfer
 //!         dma_rx: periph_dma1_ch5!(reg),
 //!         dma_rx_int: thr.dma1_ch5,
-//!         dma_rx_ch: 1,    // I2C1_RX
-//!         dma_rx_pl: 0b11, // very high
-//!     });
+//!         dma_rx_ch: 1,        // I2C1_RX
+//!         dma_rx_pl: 0b11,     // very high
+//!         dma_rx_mburst: 0b00, // single transfer
+//!         on_error: None,      // panic on DMA errors
+//!     })
+//!     .expect("invalid I2C setup");
 //! }
 //! # fn main() {
 //! #     unsafe { handler(Regs::take(), ThrsInit::take()) };
@@ -161,7 +174,7 @@
 //! #         };
 //! #     }
 //! # }
-//! # async fn handler() {
+//! # async fn handler() -> Result<(), smartoris_i2c::I2CError> {
 //! # let mut i2c1: smartoris_i2c::I2CDrv<
 //! #     I2C1,
 //! #     thr::I2C1Ev,
@@ -172,14 +185,12 @@
 //! #     thr::Dma1Ch5,
 //! # > = unsafe { core::mem::MaybeUninit::uninit().assume_init() };
 //! let buf = vec![0x92, 0, 0, 0].into_boxed_slice();
-//! let buf = i2c1
-//!     .master(buf) // create a master session backed by the given buffer
-//!     .write(0x39, ..1) // write the first byte from the buffer to address `0x39`
-//!     .await
-//!     .read(0x39, ..) // read 4 bytes into the buffer from address `0x39`
-//!     .await
-//!     .stop(); // release the bus and get the buffer back
+//! let mut master = i2c1.master(buf); // create a master session backed by the given buffer
+//! master.write(0x39, ..1).await?; // write the first byte from the buffer to address `0x39`
+//! master.read(0x39, ..).await?; // read 4 bytes into the buffer from address `0x39`
+//! let buf = master.stop(); // release the bus and get the buffer back
 //! println!("{:?}", buf);
+//! # Ok(())
 //! # }
 //! # fn main() {}
 //! ```
@@ -203,13 +214,55 @@
 )]
 #![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "ack-timing")]
+mod ack_timing;
+mod addr;
+mod blocking;
 mod diverged;
+mod dma_flags;
+mod dma_safe;
 mod drv;
+#[cfg(feature = "dry-run")]
+mod dry_run;
+mod error;
+mod fsm;
+mod hang;
+mod int_drv;
+mod int_master;
+mod lock;
 mod master;
+mod mux;
+mod nack;
+mod slave;
+mod status;
+mod timing;
+mod transaction;
 
+#[cfg(feature = "ack-timing")]
+pub use self::ack_timing::I2CAckStats;
+#[cfg(feature = "dry-run")]
+pub use self::dry_run::I2COp;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub use self::fsm::{ev_transition, I2CEvAction, I2CEvFlags, I2CEvState};
 pub use self::{
-    drv::{I2CDrv, I2CMode, I2CSetup},
+    addr::I2CAddr,
+    dma_flags::{I2CDmaChFlags, I2CDmaFlags},
+    dma_safe::DmaSafe,
+    drv::{I2CConfig, I2CDrv, I2CMode, I2CSetup, I2CSetupBuilder, I2CSmbusMode},
+    error::{I2CConfigError, I2CError, I2CErrorKind},
+    hang::I2CHangDiagnosis,
+    int_drv::{I2CIntDrv, I2CIntSetup},
+    int_master::I2CIntMaster,
+    lock::{I2CLock, I2CLockFuture, I2CLockGuard},
     master::I2CMaster,
+    mux::I2CMux,
+    slave::{I2CSlave, I2CSlaveReq},
+    status::I2CStatus,
+    timing::I2CTimingReport,
+    transaction::I2CTransactionOp,
 };
 
 #[prelude_import]
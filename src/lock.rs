@@ -0,0 +1,258 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::size_of,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use drone_core::sync::LinkedList;
+
+const MAX_WAITERS: usize = size_of::<usize>() * 8;
+
+/// Fair lock for sharing an I²C driver between tasks.
+///
+/// Unlike [`drone_core::sync::Mutex`], which lets any contending task take
+/// over a released lock, this lock is granted in the first-in, first-out
+/// order: each [`I2CLock::lock`] call takes a ticket, and the tickets are
+/// served in the order they were taken. A task polling a bus in a tight loop
+/// can't starve a task waiting for the bus, because its next `lock` call is
+/// queued behind the waiting one.
+///
+/// A lock future dropped before acquiring the lock gives up its place in the
+/// queue. At most [`usize::BITS`] tasks, i.e. 32 on Cortex-M, can wait for the
+/// lock at once.
+///
+/// The lock is a plain future, so it works with any Drone thread executor.
+/// Waiting tasks are woken on every release, and the ones not served yet
+/// return to waiting.
+pub struct I2CLock<T> {
+    next: AtomicUsize,
+    serving: AtomicUsize,
+    cancelled: AtomicUsize,
+    wakers: LinkedList<Waker>,
+    data: UnsafeCell<T>,
+}
+
+/// RAII guard of [`I2CLock`]. The lock is released when the guard is dropped.
+#[must_use = "if unused the I2CLock will immediately unlock"]
+pub struct I2CLockGuard<'a, T> {
+    lock: &'a I2CLock<T>,
+}
+
+/// Future returned by [`I2CLock::lock`].
+pub struct I2CLockFuture<'a, T> {
+    lock: &'a I2CLock<T>,
+    ticket: usize,
+    acquired: bool,
+}
+
+unsafe impl<T: Send> Send for I2CLock<T> {}
+unsafe impl<T: Send> Sync for I2CLock<T> {}
+unsafe impl<T: Send> Send for I2CLockGuard<'_, T> {}
+unsafe impl<T: Sync> Sync for I2CLockGuard<'_, T> {}
+unsafe impl<T: Send> Send for I2CLockFuture<'_, T> {}
+
+impl<T> I2CLock<T> {
+    /// Creates a new unlocked lock around `data`.
+    pub const fn new(data: T) -> Self {
+        Self {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            cancelled: AtomicUsize::new(0),
+            wakers: LinkedList::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Takes a place in the queue, and returns a future, which resolves when
+    /// the lock is granted to this place.
+    ///
+    /// # Panics
+    ///
+    /// If there are too many waiting tasks.
+    pub fn lock(&self) -> I2CLockFuture<'_, T> {
+        let ticket = self.next.fetch_add(1, Ordering::SeqCst);
+        assert!(
+            ticket.wrapping_sub(self.serving.load(Ordering::SeqCst)) < MAX_WAITERS,
+            "Too many I2CLock waiters"
+        );
+        I2CLockFuture { lock: self, ticket, acquired: false }
+    }
+
+    /// Returns a mutable reference to the underlying data. No locking is
+    /// needed, since the lock is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Consumes the lock and returns the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn release(&self, ticket: usize) {
+        let mut serving = ticket.wrapping_add(1);
+        self.serving.store(serving, Ordering::SeqCst);
+        // skip the tickets of the dropped futures
+        while self.take_cancelled(serving) {
+            serving = serving.wrapping_add(1);
+            self.serving.store(serving, Ordering::SeqCst);
+        }
+        while let Some(waker) = self.wakers.pop() {
+            waker.wake();
+        }
+    }
+
+    fn cancel(&self, ticket: usize) {
+        self.cancelled.fetch_or(cancel_bit(ticket), Ordering::SeqCst);
+        // if the ticket is already being served, no release will skip it
+        if self.serving.load(Ordering::SeqCst) == ticket && self.take_cancelled(ticket) {
+            self.release(ticket);
+        }
+    }
+
+    fn take_cancelled(&self, ticket: usize) -> bool {
+        let bit = cancel_bit(ticket);
+        self.cancelled.fetch_and(!bit, Ordering::SeqCst) & bit != 0
+    }
+}
+
+impl<'a, T> Future for I2CLockFuture<'a, T> {
+    type Output = I2CLockGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+        if lock.serving.load(Ordering::SeqCst) != self.ticket {
+            lock.wakers.push(cx.waker().clone());
+            // the lock could be released before the waker is registered
+            if lock.serving.load(Ordering::SeqCst) != self.ticket {
+                return Poll::Pending;
+            }
+        }
+        self.acquired = true;
+        Poll::Ready(I2CLockGuard { lock })
+    }
+}
+
+impl<T> Drop for I2CLockFuture<'_, T> {
+    fn drop(&mut self) {
+        if !self.acquired {
+            self.lock.cancel(self.ticket);
+        }
+    }
+}
+
+impl<T> Deref for I2CLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for I2CLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for I2CLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(self.lock.serving.load(Ordering::SeqCst));
+    }
+}
+
+fn cancel_bit(ticket: usize) -> usize {
+    1 << (ticket % MAX_WAITERS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+
+    fn poll<'a>(future: &mut I2CLockFuture<'a, ()>) -> Poll<I2CLockGuard<'a, ()>> {
+        Pin::new(future).poll(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    fn wrapping_lock(start: usize) -> I2CLock<()> {
+        let lock = I2CLock::new(());
+        lock.next.store(start, Ordering::SeqCst);
+        lock.serving.store(start, Ordering::SeqCst);
+        lock
+    }
+
+    #[test]
+    fn fifo_order() {
+        let lock = I2CLock::new(());
+        let mut a = lock.lock();
+        let mut b = lock.lock();
+        let mut c = lock.lock();
+        let guard_a = poll(&mut a);
+        assert!(guard_a.is_ready());
+        assert!(poll(&mut c).is_pending());
+        assert!(poll(&mut b).is_pending());
+        drop(guard_a);
+        // the later ticket doesn't overtake the earlier one
+        assert!(poll(&mut c).is_pending());
+        let guard_b = poll(&mut b);
+        assert!(guard_b.is_ready());
+        drop(guard_b);
+        assert!(poll(&mut c).is_ready());
+    }
+
+    #[test]
+    fn cancel_served_waiter() {
+        let lock = I2CLock::new(());
+        let mut a = lock.lock();
+        let b = lock.lock();
+        let guard_a = poll(&mut a);
+        assert!(guard_a.is_ready());
+        drop(guard_a);
+        // the lock is granted to `b`, which is dropped before polling
+        assert_eq!(lock.serving.load(Ordering::SeqCst), 1);
+        drop(b);
+        assert_eq!(lock.serving.load(Ordering::SeqCst), 2);
+        assert!(poll(&mut lock.lock()).is_ready());
+    }
+
+    #[test]
+    fn cancel_queued_waiter() {
+        let lock = I2CLock::new(());
+        let mut a = lock.lock();
+        let b = lock.lock();
+        let mut c = lock.lock();
+        let guard_a = poll(&mut a);
+        assert!(guard_a.is_ready());
+        drop(b);
+        assert!(poll(&mut c).is_pending());
+        drop(guard_a);
+        // the ticket of `b` is skipped
+        assert!(poll(&mut c).is_ready());
+        assert_eq!(lock.cancelled.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn ticket_wrap_around() {
+        let lock = wrapping_lock(usize::MAX);
+        let mut a = lock.lock();
+        let b = lock.lock();
+        let mut c = lock.lock();
+        assert_eq!((a.ticket, b.ticket, c.ticket), (usize::MAX, 0, 1));
+        let guard_a = poll(&mut a);
+        assert!(guard_a.is_ready());
+        drop(b);
+        assert!(poll(&mut c).is_pending());
+        drop(guard_a);
+        assert!(poll(&mut c).is_ready());
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many I2CLock waiters")]
+    fn too_many_waiters() {
+        let lock = wrapping_lock(usize::MAX - 1);
+        let _waiters = (0..=MAX_WAITERS).map(|_| lock.lock()).collect::<Vec<_>>();
+    }
+}
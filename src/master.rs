@@ -1,13 +1,112 @@
-use crate::I2CDrv;
-use core::{mem::ManuallyDrop, slice::SliceIndex};
-use drone_cortexm::thr::prelude::*;
+#[cfg(feature = "dry-run")]
+use crate::I2COp;
+use crate::{drv::RxPrearm, fsm::I2CSessionStart, I2CAddr, I2CDrv, I2CError, I2CTransactionOp};
+use core::{
+    cell::Cell,
+    fmt,
+    future::Future,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::Range,
+    pin::Pin,
+    slice::{self, SliceIndex},
+};
+use drone_cortexm::{drv::timer::Timer, thr::prelude::*};
 use drone_stm32_map::periph::{dma::ch::DmaChMap, i2c::I2CMap};
+use futures::{
+    future::{self, Either, FutureExt},
+    pin_mut,
+    stream::StreamExt,
+};
+
+type TimeoutFn<'a> = Box<dyn FnMut() -> Pin<Box<dyn Future<Output = ()> + 'a>> + 'a>;
+type BackoffFn<'a> = Box<dyn FnMut(u8) -> Pin<Box<dyn Future<Output = ()> + 'a>> + 'a>;
 
 /// I²C master session.
 ///
 /// The session object takes ownership of the provided buffer, which is returned
-/// by [`I2CMaster::stop`] method. If the `stop` method is not called, the
-/// buffer will be leaked.
+/// by [`I2CMaster::stop`] method. If the session is dropped without calling
+/// the `stop` method, the Stop signal is sent and the buffer is freed.
+///
+/// The operations borrow the session and resolve to a [`Result`], so they can
+/// be chained with the `?` operator. If an operation fails with an
+/// [`I2CError`], the error flags are cleared and the bus is released by the
+/// driver. The session stays valid: the buffer keeps the data transferred
+/// before the error, and can be inspected with [`I2CMaster::buf`] or taken
+/// back with [`I2CMaster::stop`]. The next operation of the session begins
+/// with a fresh Start signal.
+///
+/// The data can be recovered on the error path too, e.g. to examine the bytes
+/// read before a NACK:
+///
+/// ```no_run
+/// # #![feature(const_fn_fn_ptr_basics)]
+/// # use drone_stm32_map::periph::{
+/// #     dma::ch::{Dma1Ch5, Dma1Ch6},
+/// #     i2c::I2C1,
+/// # };
+/// # mod thr {
+/// #     use drone_stm32_map::thr::*;
+/// #     drone_cortexm::thr::nvic! {
+/// #         thread => pub Thr {};
+/// #         local => pub ThrLocal {};
+/// #         vtable => pub Vtable;
+/// #         index => pub Thrs;
+/// #         init => pub ThrsInit;
+/// #         threads => {
+/// #             interrupts => {
+/// #                 16: pub dma1_ch5;
+/// #                 17: pub dma1_ch6;
+/// #                 31: pub i2c1_ev;
+/// #                 32: pub i2c1_er;
+/// #             };
+/// #         };
+/// #     }
+/// # }
+/// # async fn handler() {
+/// # let mut i2c1: smartoris_i2c::I2CDrv<
+/// #     I2C1,
+/// #     thr::I2C1Ev,
+/// #     thr::I2C1Er,
+/// #     Dma1Ch6,
+/// #     thr::Dma1Ch6,
+/// #     Dma1Ch5,
+/// #     thr::Dma1Ch5,
+/// # > = unsafe { core::mem::MaybeUninit::uninit().assume_init() };
+/// let mut master = i2c1.master(vec![0x92, 0, 0, 0].into_boxed_slice());
+/// let result = async {
+///     master.write(0x39, ..1).await?;
+///     master.read(0x39, 1..).await
+/// }
+/// .await;
+/// let buf = master.stop(); // the buffer is returned on both paths
+/// if result.is_err() {
+///     // examine `buf` with the partial data
+/// }
+/// # }
+/// # fn main() {}
+/// ```
+///
+/// If an operation future is dropped before completion, e.g. cancelled by a
+/// timeout, its transfer is aborted the same way as on a bus error: the DMA
+/// streams and the event interrupt are disabled, and the Stop signal is sent.
+/// The drop returns after the DMA stops accessing the buffer, so the operation
+/// futures can be composed with `select` or timeouts, and the session can be
+/// used right away. Temporary buffers of the operations, which stage their
+/// data, are freed after the transfer is aborted. The transfer of a leaked
+/// operation future, e.g. with [`core::mem::forget`], is aborted by the next
+/// use of the session.
+///
+/// The first operation of the session waits until the bus is not busy, i.e.
+/// SR2.BUSY is cleared, so a transfer of another master on a multi-master bus
+/// isn't corrupted by our Start signal. The wait can be bounded with
+/// [`I2CMaster::set_timeout`].
+///
+/// The buffer container is the `B` type parameter, which is a [`Box`] by
+/// default. A session created by [`I2CDrv::master_with`] holds any
+/// [`DmaSafe`](crate::DmaSafe) container, and [`I2CDrv::master_borrowed`]
+/// borrows a slice. A session with a borrowed slice must not be leaked while
+/// an operation is in progress.
+#[allow(clippy::struct_excessive_bools)]
 pub struct I2CMaster<
     'a,
     I2C: I2CMap,
@@ -17,9 +116,52 @@ pub struct I2CMaster<
     DmaTxInt: IntToken,
     DmaRx: DmaChMap,
     DmaRxInt: IntToken,
+    B: AsRef<[u8]> + AsMut<[u8]> = Box<[u8]>,
 > {
     drv: &'a mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
-    buf: ManuallyDrop<Box<[u8]>>,
+    buf: ManuallyDrop<B>,
+    has_buf: bool,
+    in_flight: Cell<bool>,
+    start: I2CSessionStart,
+    segment: Option<u8>,
+    addr: Option<u8>,
+    retries: u8,
+    backoff: Option<BackoffFn<'a>>,
+    timeout: Option<TimeoutFn<'a>>,
+}
+
+/// Restarts left for a single operation.
+struct Retries {
+    arbitration: u8,
+    recoverable: u8,
+    attempt: u8,
+}
+
+/// Destination of a read.
+#[derive(Clone)]
+enum ReadInto {
+    /// The session buffer slice of the range.
+    Session(Range<usize>),
+    /// A `'static` buffer of the address and the length.
+    Static(usize, usize),
+}
+
+impl ReadInto {
+    #[cfg(feature = "dry-run")]
+    fn len(&self) -> usize {
+        match self {
+            Self::Session(range) => range.len(),
+            Self::Static(_, len) => *len,
+        }
+    }
+
+    /// Returns the destination slice, given the session buffer `buf`.
+    unsafe fn slice(self, buf: &mut [u8]) -> &mut [u8] {
+        match self {
+            Self::Session(range) => &mut buf[range],
+            Self::Static(ptr, len) => slice::from_raw_parts_mut(ptr as *mut u8, len),
+        }
+    }
 }
 
 impl<
@@ -31,56 +173,1421 @@ impl<
     DmaTxInt: IntToken,
     DmaRx: DmaChMap,
     DmaRxInt: IntToken,
-> I2CMaster<'a, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+    B: AsRef<[u8]> + AsMut<[u8]>,
+> I2CMaster<'a, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, B>
 {
     pub(crate) fn new(
         drv: &'a mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
-        buf: Box<[u8]>,
+        buf: B,
     ) -> Self {
-        Self { drv, buf: ManuallyDrop::new(buf) }
+        Self {
+            drv,
+            buf: ManuallyDrop::new(buf),
+            has_buf: true,
+            in_flight: Cell::new(false),
+            start: I2CSessionStart::default(),
+            segment: None,
+            addr: None,
+            retries: 0,
+            backoff: None,
+            timeout: None,
+        }
+    }
+
+    pub(crate) fn new_empty(
+        drv: &'a mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+    ) -> Self
+    where
+        B: Default,
+    {
+        let mut session = Self::new(drv, B::default());
+        session.has_buf = false;
+        session
     }
 
     /// Sends the Start signal for the address `addr`, and writes the data from
     /// the session buffer slice of the range `index` to the slave.
-    pub async fn write<I: SliceIndex<[u8], Output = [u8]>>(
-        self,
-        addr: u8,
+    ///
+    /// If this is not the first operation of the session, the Start signal is
+    /// a repeated Start.
+    ///
+    /// Slices exceeding the DMA transfer size limit are transferred in several
+    /// DMA runs without releasing the bus.
+    ///
+    /// On a multi-master bus, a lost arbitration can be retried, see
+    /// [`I2CDrv::set_arbitration_retries`]. Other recoverable errors can be
+    /// retried as well, see [`I2CMaster::set_retries`].
+    ///
+    /// An empty range performs an address-only write, which is the usual way
+    /// to probe for a device. An absent device doesn't acknowledge the
+    /// address, and the operation fails with [`I2CError::Nack`]. The Stop
+    /// signal is sent by the driver, so the next session can be started
+    /// normally.
+    ///
+    /// `addr` can be a 7-bit address, or an [`I2CAddr`] of either width.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty with a 10-bit address.
+    pub async fn write<A: Into<I2CAddr>, I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: A,
         index: I,
-    ) -> I2CMaster<'a, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
-        unsafe { self.drv.write(addr, &self.buf[index]).await };
-        self
+    ) -> Result<(), I2CError> {
+        let addr = addr.into();
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write {
+            addr: addr.bit7(),
+            data: self.buf.as_ref()[range.clone()].to_vec(),
+        }) {
+            return Ok(());
+        }
+        let mut retries = self.retries();
+        loop {
+            let range = range.clone();
+            self.run_transfer(|drv, buf| unsafe { drv.write(addr, &buf[range]) }).await;
+            if !self.retry(&mut retries).await? {
+                break;
+            }
+        }
+        self.check_error()
     }
 
     /// Sends the Start signal for the address `addr`, and reads the data from
     /// the slave into the session buffer slice of the range `index`.
-    pub async fn read<I: SliceIndex<[u8], Output = [u8]>>(
-        mut self,
+    ///
+    /// If this is not the first operation of the session, the Start signal is
+    /// a repeated Start.
+    ///
+    /// Slices exceeding the DMA transfer size limit are transferred in several
+    /// DMA runs without releasing the bus.
+    ///
+    /// On a multi-master bus, a lost arbitration can be retried, see
+    /// [`I2CDrv::set_arbitration_retries`]. Other recoverable errors can be
+    /// retried as well, see [`I2CMaster::set_retries`].
+    ///
+    /// An empty range performs an address-only read, which completes as soon
+    /// as the slave acknowledges its address. This tells a present device
+    /// from an absent one, which fails the operation with [`I2CError::Nack`].
+    /// Note that a present slave, which has no data to give, can't be
+    /// detected by a non-empty read: it still acknowledges the address, and
+    /// the data bytes are then device-specific, usually 0xFF from the released
+    /// SDA line.
+    ///
+    /// `addr` can be a 7-bit address, or an [`I2CAddr`] of either width.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty with a 10-bit address.
+    pub async fn read<A: Into<I2CAddr>, I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: A,
+        index: I,
+    ) -> Result<(), I2CError> {
+        let range = self.slice_bounds(index);
+        self.read_into(addr.into(), ReadInto::Session(range)).await
+    }
+
+    /// Reads the data from the slave at the address `addr` into `dst`.
+    async fn read_into(&mut self, addr: I2CAddr, dst: ReadInto) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr: addr.bit7(), len: dst.len() }) {
+            if let ReadInto::Static(ptr, len) = dst {
+                unsafe { (ptr as *mut u8).write_bytes(0, len) };
+            }
+            return Ok(());
+        }
+        let mut retries = self.retries();
+        loop {
+            let dst = dst.clone();
+            self.run_transfer(|drv, buf| unsafe { drv.read(addr, dst.slice(buf)) }).await;
+            if !self.retry(&mut retries).await? {
+                break;
+            }
+        }
+        self.check_error()
+    }
+
+    /// Same as [`I2CMaster::read`] of `range`, but the first attempt starts the
+    /// DMA Rx stream pre-armed by [`I2CDrv::prearm_rx`]. The retries fall back
+    /// to the regular read.
+    async fn read_prearmed<T: Future<Output = ()>>(
+        &mut self,
+        addr: I2CAddr,
+        range: Range<usize>,
+        prearm: RxPrearm<T>,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let mut prearm = Some(prearm);
+        let mut retries = self.retries();
+        loop {
+            let range = range.clone();
+            match prearm.take() {
+                Some(prearm) => {
+                    self.run_transfer(|drv, _| unsafe { drv.read_prearmed(addr, prearm) }).await;
+                }
+                None => {
+                    self.run_transfer(|drv, buf| unsafe { drv.read(addr, &mut buf[range]) }).await;
+                }
+            }
+            if !self.retry(&mut retries).await? {
+                break;
+            }
+        }
+        self.check_error()
+    }
+
+    /// Sends the Start signal for the address `addr`, and reads the data from
+    /// the slave into the uninitialized `buf`, returning it as an initialized
+    /// slice.
+    ///
+    /// This is the same as [`I2CMaster::read`], but `buf` doesn't need to be
+    /// zeroed beforehand, which is wasted work for large reads. The DMA or the
+    /// event interrupt handler writes every byte of `buf` before the operation
+    /// completes successfully. In the dry-run mode, `buf` is zeroed.
+    ///
+    /// `buf` is `'static`, so that it stays valid and inaccessible to the
+    /// caller even if the future is leaked, e.g. with [`core::mem::forget`],
+    /// while the DMA is still writing to it. Retries are performed the same
+    /// way as for [`I2CMaster::read`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. `buf` is returned back with the error, its
+    /// contents are unspecified, and the bus is released, see [`I2CMaster`].
+    #[allow(clippy::type_complexity)]
+    pub async fn read_uninit(
+        &mut self,
+        addr: u8,
+        buf: &'static mut [MaybeUninit<u8>],
+    ) -> Result<&'static mut [u8], (&'static mut [MaybeUninit<u8>], I2CError)> {
+        let (ptr, len) = (buf.as_mut_ptr() as usize, buf.len());
+        match self.read_into(I2CAddr::Bit7(addr), ReadInto::Static(ptr, len)).await {
+            // every byte is written by the DMA or the event interrupt handler
+            Ok(()) => Ok(unsafe { slice::from_raw_parts_mut(ptr as *mut u8, len) }),
+            Err(err) => Err((buf, err)),
+        }
+    }
+
+    /// Reads the data from the slave at the address `addr` into the session
+    /// buffer slice of the range `index`, calling `half` when the first half
+    /// of the slice is filled.
+    ///
+    /// `half` is called from the task with the first half of the slice, once
+    /// the DMA Rx half transfer interrupt signals it's filled, while the second
+    /// half is being received. This allows to start processing a large inbound
+    /// transfer before it's finished. `half` is not called if the transfer
+    /// fails or is aborted before the first half is filled. Otherwise it's the
+    /// same as [`I2CMaster::read`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is shorter than two bytes, or exceeds the DMA
+    /// transfer size limit.
+    pub async fn read_with_half<I: SliceIndex<[u8], Output = [u8]>, F: FnMut(&[u8])>(
+        &mut self,
+        addr: u8,
+        index: I,
+        mut half: F,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        let half = &mut half;
+        self.run_transfer(|drv, buf| async move {
+            let buf_rx = &mut buf[range];
+            let (ptr, len) = (buf_rx.as_ptr(), buf_rx.len() / 2);
+            let (transfer, filled) = unsafe { drv.read_half(addr, buf_rx) };
+            pin_mut!(transfer);
+            match future::select(transfer, filled).await {
+                Either::Left(((), _)) => {}
+                Either::Right((filled, transfer)) => {
+                    if filled && !drv.has_error() {
+                        // the DMA doesn't write to the first half anymore
+                        half(unsafe { slice::from_raw_parts(ptr, len) });
+                    }
+                    transfer.await;
+                }
+            }
+        })
+        .await;
+        self.check_error()
+    }
+
+    /// Writes the data from the session buffer slice of the range `tx_index`
+    /// to the slave at the address `addr`, and then reads the data from the
+    /// same slave into the session buffer slice of the range `rx_index`.
+    ///
+    /// This is the common "write a register pointer, then read" transaction.
+    /// The read is started with a repeated Start, and the bus is held after
+    /// it until the next operation or [`I2CMaster::stop`]. It's the same as
+    /// [`I2CMaster::write`] followed by [`I2CMaster::read`].
+    ///
+    /// If the read part uses the DMA, the DMA Rx stream is pre-armed before
+    /// the write: the memory address, the transfer length, and the stream
+    /// interrupt handler are set up while the bus is still free. Between the
+    /// write end and the repeated Start, while SCL is held low, only
+    /// the stream enable is left. A retried read is set up the regular way.
+    ///
+    /// The read is started only if the slave acknowledged every byte of the
+    /// write part, so a register pointer written partially never leads to a
+    /// read from a wrong register.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`]. If the
+    /// slave doesn't acknowledge a byte of the write part, the read is not
+    /// started, and [`I2CError::Nack`] carries the number of the write bytes
+    /// the slave acknowledged.
+    ///
+    /// # Panics
+    ///
+    /// If the range `tx_index` is empty.
+    pub async fn write_read<
+        A: Into<I2CAddr>,
+        I: SliceIndex<[u8], Output = [u8]>,
+        J: SliceIndex<[u8], Output = [u8]>,
+    >(
+        &mut self,
+        addr: A,
+        tx_index: I,
+        rx_index: J,
+    ) -> Result<(), I2CError> {
+        let addr = addr.into();
+        let range = self.slice_bounds(tx_index);
+        assert!(!range.is_empty(), "Empty write part");
+        let rx_range = self.slice_bounds(rx_index);
+        #[cfg(feature = "dry-run")]
+        if self.drv.is_dry_run() {
+            self.write(addr, range).await?;
+            return self.read(addr, rx_range).await;
+        }
+        // the Rx stream is programmed while the bus is still free, and the
+        // write is in progress, so that only the stream enable is left between
+        // the write end and the repeated Start
+        let prearm = unsafe { self.drv.prearm_rx(addr, &mut self.buf.as_mut()[rx_range.clone()]) };
+        self.write(addr, range).await?;
+        match prearm {
+            Some(prearm) => self.read_prearmed(addr, rx_range, prearm).await,
+            None => self.read(addr, rx_range).await,
+        }
+    }
+
+    /// Performs a combined transaction of `ops` with the slave at the address
+    /// `addr`, and then sends the Stop signal.
+    ///
+    /// The operations are executed back-to-back, each one starting with a
+    /// repeated Start, so the bus is held for the whole transaction and
+    /// exactly one Stop signal is sent at the end. It's the same as chaining
+    /// [`I2CMaster::write`] and [`I2CMaster::read`] calls followed by
+    /// [`I2CMaster::stop`], but the session buffer stays in the session. If
+    /// the session already holds the bus, the first operation is started with
+    /// a repeated Start as well. The next operation of the session begins with
+    /// a fresh Start signal.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The remaining operations are not performed, and
+    /// the bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If `ops` is empty, or if a range is out of the session buffer bounds.
+    pub async fn transaction<A: Into<I2CAddr>>(
+        &mut self,
+        addr: A,
+        ops: &[I2CTransactionOp],
+    ) -> Result<(), I2CError> {
+        assert!(!ops.is_empty(), "Empty transaction");
+        let addr = addr.into();
+        for op in ops {
+            match op {
+                I2CTransactionOp::Write(range) => self.write(addr, range.clone()).await?,
+                I2CTransactionOp::Read(range) => self.read(addr, range.clone()).await?,
+            }
+        }
+        self.release_bus();
+        Ok(())
+    }
+
+    /// Performs the SMBus Quick Command: sends the Start signal for the address
+    /// `addr` with the direction bit `read`, and then the Stop signal.
+    ///
+    /// No data bytes are transferred, and no DMA transfer is armed. The
+    /// direction bit itself carries the command, e.g. to switch a device on or
+    /// off. The session buffer is not used, so this works with a session
+    /// created by [`I2CDrv::master_empty`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs, e.g. the address is not acknowledged. The
+    /// bus is released, see [`I2CMaster`].
+    pub async fn quick(&mut self, addr: u8, read: bool) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        #[cfg(feature = "dry-run")]
+        let dry_run = if read {
+            self.drv.dry_run(I2COp::Read { addr, len: 0 })
+        } else {
+            self.drv.dry_run(I2COp::Write { addr, data: Vec::new() })
+        };
+        #[cfg(not(feature = "dry-run"))]
+        let dry_run = false;
+        if !dry_run {
+            self.run_transfer(|drv, _| {
+                if read {
+                    unsafe { drv.read(I2CAddr::Bit7(addr), &mut []) }.left_future()
+                } else {
+                    unsafe { drv.write(I2CAddr::Bit7(addr), &[]) }.right_future()
+                }
+            })
+            .await;
+            self.check_error()?;
+        }
+        self.release_bus();
+        Ok(())
+    }
+
+    /// Sends the Start signal for the address `addr`, and writes the data from
+    /// the session buffer slice of the range `index` to the slave, followed by
+    /// the SMBus packet error checking (PEC) byte.
+    ///
+    /// The PEC byte is calculated by the hardware over the address and the
+    /// data, and is sent after the last data byte. The session buffer doesn't
+    /// need room for it.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, or exceeds the DMA transfer size limit.
+    pub async fn write_pec<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write { addr, data: self.buf.as_ref()[range.clone()].to_vec() })
+        {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.write_pec(addr, &buf[range]) }).await;
+        self.drv.end_pec();
+        self.check_error()
+    }
+
+    /// Sends the Start signal for the address `addr`, and reads the data
+    /// followed by the SMBus packet error checking (PEC) byte from the slave
+    /// into the session buffer slice of the range `index`.
+    ///
+    /// The last byte of the range receives the PEC byte, which is checked by
+    /// the hardware against the one calculated over the address and the data.
+    /// The PEC calculation starts with this read, so it suits the SMBus
+    /// transactions, which begin with a read. For the ones beginning with a
+    /// command write, use [`I2CMaster::write_read_pec`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs, or the PEC byte doesn't match with
+    /// [`I2CError::Pec`]. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is shorter than two bytes, or exceeds the DMA
+    /// transfer size limit.
+    pub async fn read_pec<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.read_pec(addr, &mut buf[range]) }).await;
+        self.drv.end_pec();
+        self.check_error()
+    }
+
+    /// Writes the data from the session buffer slice of the range `tx_index`
+    /// to the slave at the address `addr`, and then reads the data followed by
+    /// the SMBus packet error checking (PEC) byte from the same slave into the
+    /// session buffer slice of the range `rx_index`.
+    ///
+    /// This is the SMBus Read Byte/Word and Block Read transaction. The PEC
+    /// byte is checked over both parts, including the command write. The
+    /// write part is driven by the I²C event interrupt, because the hardware
+    /// sends the PEC byte after each DMA transmission. See
+    /// [`I2CMaster::read_pec`] for the read part.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs, or the PEC byte doesn't match with
+    /// [`I2CError::Pec`]. The bus is released, see [`I2CMaster`]. If the slave
+    /// doesn't acknowledge a byte of the write part, the read is not started,
+    /// and [`I2CError::Nack`] carries the number of the write bytes the slave
+    /// acknowledged.
+    ///
+    /// # Panics
+    ///
+    /// If the range `tx_index` is empty, the range `rx_index` is shorter than
+    /// two bytes, or exceeds the DMA transfer size limit.
+    pub async fn write_read_pec<
+        I: SliceIndex<[u8], Output = [u8]>,
+        J: SliceIndex<[u8], Output = [u8]>,
+    >(
+        &mut self,
+        addr: u8,
+        tx_index: I,
+        rx_index: J,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(tx_index);
+        assert!(!range.is_empty(), "Empty write part");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write { addr, data: self.buf.as_ref()[range.clone()].to_vec() })
+        {
+            return self.read_pec(addr, rx_index).await;
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.write_no_pec(addr, &buf[range]) }).await;
+        self.check_error()?;
+        self.read_pec(addr, rx_index).await
+    }
+
+    /// Reads the data from the slave at the address `addr` into the session
+    /// buffer slice of the range `index`, and checks it with `predicate`. If
+    /// the check fails, repeats the read up to `retries` more times.
+    ///
+    /// This catches transient bus corruption that doesn't trigger a hardware
+    /// error, but produces wrong data, e.g. a wrong identification register
+    /// value. Each attempt is a separate read transfer, which begins with a
+    /// repeated Start if the bus is held. Use this only for idempotent reads:
+    /// reading a FIFO or a clear-on-read status register would lose data on
+    /// a retry.
+    ///
+    /// Returns `true` if the data passed the check, or `false` if all attempts
+    /// failed.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs on any attempt. The bus is released, see
+    /// [`I2CMaster`].
+    pub async fn read_validated<
+        I: SliceIndex<[u8], Output = [u8]> + Clone,
+        P: FnMut(&[u8]) -> bool,
+    >(
+        &mut self,
+        addr: u8,
+        index: I,
+        mut predicate: P,
+        retries: usize,
+    ) -> Result<bool, I2CError> {
+        for _ in 0..=retries {
+            self.read(addr, index.clone()).await?;
+            if predicate(&self.buf.as_ref()[index.clone()]) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Sends the Start signal for the address `addr`, discards the first `skip`
+    /// bytes from the slave, and reads the following data into the session
+    /// buffer slice of the range `index`.
+    ///
+    /// This is for devices, which send dummy bytes before valid data, e.g. some
+    /// ADCs and flash memories. The dummy bytes are received into a temporary
+    /// buffer of `skip` bytes as a separate segment of the same transfer, so
+    /// the session buffer needs room only for the valid data. With `skip` set
+    /// to zero, this is the same as [`I2CMaster::read`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If `skip` is not zero and the range `index` is empty.
+    pub async fn read_skip<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+        skip: usize,
+    ) -> Result<(), I2CError> {
+        if skip == 0 {
+            return self.read(addr, index).await;
+        }
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty read segment");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: skip + range.len() }) {
+            return Ok(());
+        }
+        // declared before the transfer, so it's freed after an aborted transfer
+        let mut dummy = vec![0; skip];
+        let dummy = &mut dummy;
+        self.run_transfer(|drv, buf| async move {
+            unsafe {
+                drv.read_segment(Some(addr), dummy, false).await;
+                if !drv.has_error() {
+                    drv.read_segment(None, &mut buf[range], true).await;
+                }
+            }
+        })
+        .await;
+        self.check_error()
+    }
+
+    /// Reads a segment of a read transfer from the slave at the address `addr`
+    /// into the session buffer slice of the range `index`.
+    ///
+    /// The first segment sends the Start signal for the address. The following
+    /// segments continue the same transfer without a Start signal, until a
+    /// segment with `last` set. The last byte of an intermediate segment is
+    /// acknowledged, so that the slave keeps sending, and only the final byte
+    /// of the transfer is not acknowledged. The clock is stretched by the
+    /// peripheral between segments.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, if `addr` differs from the address of the
+    /// unfinished segmented read, or if another operation is started before
+    /// the segmented read is finished.
+    pub async fn read_segment<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+        last: bool,
+    ) -> Result<(), I2CError> {
+        self.settle();
+        let start = if let Some(segment) = self.segment {
+            assert_eq!(segment, addr, "Segmented read address mismatch");
+            None
+        } else {
+            self.begin(addr).await?;
+            Some(addr)
+        };
+        self.segment = if last { None } else { Some(addr) };
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty read segment");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.read_segment(start, &mut buf[range], last) })
+            .await;
+        self.check_error()
+    }
+
+    /// Reads a continuous stream of data from the slave at the address `addr`
+    /// into the session buffer slice of the range `index`, which is used as a
+    /// double buffer.
+    ///
+    /// The slice is split into two halves, and the DMA stream is set up in the
+    /// double buffer mode, so that it switches between the halves without CPU
+    /// intervention and without gaps in the transfer. Each time a half is
+    /// filled, `f` is called with it, while the other half is being filled.
+    /// The read goes on until `f` returns `false`. After that the half being
+    /// filled is received, with its last byte not acknowledged, and is not
+    /// passed to `f`. The bus is held after the read until the next operation
+    /// or [`I2CMaster::stop`].
+    ///
+    /// This is for high-rate acquisition from streaming devices, e.g. a sensor
+    /// FIFO, where [`I2CMaster::read`] calls add latency between transfers.
+    /// `f` is called from the task, so it must return before the other half is
+    /// filled.
+    ///
+    /// The stream is open-ended, so the session timeout, see
+    /// [`I2CMaster::set_timeout`], doesn't bound the whole operation. Instead a
+    /// new timeout is started for the address phase and for each wait for a
+    /// filled half, so it should cover the time of receiving a single half.
+    /// The time spent in `f` is not included.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs, [`I2CError::Overrun`] if `f` doesn't return in
+    /// time and a half is overwritten, or [`I2CError::Timeout`] if a half is
+    /// not filled in time. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the length of the range `index` is odd, less than four bytes, or its
+    /// half exceeds the DMA transfer size limit.
+    pub async fn read_stream<I: SliceIndex<[u8], Output = [u8]>, F: FnMut(&[u8]) -> bool>(
+        &mut self,
+        addr: u8,
+        index: I,
+        mut f: F,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        let half = range.len() / 2;
+        assert!(
+            range.len() % 2 == 0 && half >= 2 && half <= 0xFFFF,
+            "Stream buffer length out of range"
+        );
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        let Self { drv, buf, in_flight, timeout, .. } = self;
+        let (buf0, buf1) = (**buf).as_mut()[range].split_at_mut(half);
+        let (ptr0, ptr1) = (buf0.as_ptr(), buf1.as_ptr());
+        in_flight.set(true);
+        let guard = drv.cancel_guard();
+        let abort = || guard.abort(I2CError::Timeout);
+        let (start, stream) = unsafe { drv.read_stream(addr, buf0, buf1) };
+        pin_mut!(stream);
+        timed(start_timeout(timeout, drv), abort, start).await;
+        let mut current = ptr0;
+        let mut last = false;
+        // each half is timed separately
+        while let Some(pulses) = timed(start_timeout(timeout, drv), abort, stream.next()).await {
+            if last {
+                drv.read_stream_end();
+            } else if pulses.get() > 1 {
+                drv.abort(I2CError::Overrun);
+            } else {
+                // the half is not accessed by DMA until the next pulse
+                let filled = unsafe { slice::from_raw_parts(current, half) };
+                current = if current == ptr0 { ptr1 } else { ptr0 };
+                if !f(filled) {
+                    drv.read_stream_last();
+                    last = true;
+                }
+            }
+        }
+        drv.read_stream_reset();
+        guard.disarm();
+        in_flight.set(false);
+        self.check_error()
+    }
+
+    /// Writes `data` to the consecutive registers of the slave at the address
+    /// `addr`, starting from the register `reg`.
+    ///
+    /// The register address and the data block are sent in a single write
+    /// transfer, relying on the device to auto-increment its register pointer.
+    /// The data is staged in a temporary buffer, so the session buffer is left
+    /// intact.
+    ///
+    /// Some devices auto-increment the register pointer only if a flag bit is
+    /// set in the register address, e.g. the most significant bit for many
+    /// ST sensors. For such devices the flag should be included in `reg`.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    pub async fn write_regs(&mut self, addr: u8, reg: u8, data: &[u8]) -> Result<(), I2CError> {
+        // declared before the transfer, so it's freed after an aborted transfer
+        let mut block = Vec::with_capacity(data.len() + 1);
+        block.push(reg);
+        block.extend_from_slice(data);
+        self.begin(addr).await?;
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write { addr, data: block.clone() }) {
+            return Ok(());
+        }
+        let block = &block;
+        self.run_transfer(|drv, _| unsafe { drv.write(I2CAddr::Bit7(addr), block) }).await;
+        self.check_error()
+    }
+
+    /// Writes the sequence of `(register, value)` pairs to the slave at the
+    /// address `addr`.
+    ///
+    /// Each pair is written as a separate two-byte transfer, and the
+    /// transfers are chained with repeated Start signals, so the bus is held
+    /// for the whole sequence. The pairs are staged in a temporary buffer, so
+    /// the session buffer is left intact. This is suitable for fixed
+    /// initialization sequences of sensors.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The remaining pairs are not written, and the
+    /// bus is released, see [`I2CMaster`].
+    pub async fn write_sequence(&mut self, addr: u8, seq: &[(u8, u8)]) -> Result<(), I2CError> {
+        // declared before the transfers, so it's freed after an aborted transfer
+        let mut data = Vec::with_capacity(seq.len() * 2);
+        for &(reg, value) in seq {
+            data.push(reg);
+            data.push(value);
+        }
+        for pair in data.chunks(2) {
+            self.begin(addr).await?;
+            #[cfg(feature = "dry-run")]
+            if self.drv.dry_run(I2COp::Write { addr, data: pair.to_vec() }) {
+                continue;
+            }
+            self.run_transfer(|drv, _| unsafe { drv.write(I2CAddr::Bit7(addr), pair) }).await;
+            self.check_error()?;
+        }
+        Ok(())
+    }
+
+    /// Sends the Start signal for the address `addr`, and writes the bytes
+    /// produced by `iter` to the slave.
+    ///
+    /// The session buffer is filled from `iter` up to its length, and
+    /// transmitted through DMA. While the buffer is refilled with the next
+    /// bytes, the peripheral stretches SCL, so a payload longer than the
+    /// buffer is sent as a single write transfer in several DMA runs. This
+    /// avoids materializing a generated payload, e.g. a ramp, in a buffer of
+    /// its full size. The session buffer contents are overwritten.
+    ///
+    /// An empty `iter` performs an address-only write, see
+    /// [`I2CMaster::write`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The rest of `iter` is not consumed, and the
+    /// bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the session buffer is empty.
+    pub async fn write_iter<T: IntoIterator<Item = u8>>(
+        &mut self,
+        addr: u8,
+        iter: T,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        self.check_buf();
+        assert!(!self.buf().is_empty(), "Empty session buffer");
+        let mut iter = iter.into_iter().peekable();
+        #[cfg(feature = "dry-run")]
+        if self.drv.is_dry_run() {
+            self.drv.dry_run(I2COp::Write { addr, data: iter.collect() });
+            return Ok(());
+        }
+        let mut start = Some(addr);
+        loop {
+            let mut len = 0;
+            for (slot, byte) in self.buf.as_mut().iter_mut().zip(&mut iter) {
+                *slot = byte;
+                len += 1;
+            }
+            let start = start.take();
+            self.run_transfer(|drv, buf| unsafe { drv.write_segment(start, &buf[..len]) }).await;
+            self.check_error()?;
+            if iter.peek().is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the data from the session buffer slice of the range `index` to
+    /// the slave at the address `addr`, sending a separate Start signal with
+    /// the address before each byte.
+    ///
+    /// This is an advanced mode for a few unusual devices, which require a
+    /// repeated Start between every data byte. Each byte is transferred as a
+    /// separate operation, so all Start signals after the first one are
+    /// repeated Starts and the bus is held for the whole sequence. Regular
+    /// devices should be accessed with [`I2CMaster::write`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The remaining bytes are not written, and the
+    /// bus is released, see [`I2CMaster`].
+    pub async fn write_bytewise<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        for i in self.slice_bounds(index) {
+            self.write(addr, i..=i).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the data from the slave at the address `addr` into the session
+    /// buffer slice of the range `index`, sending a separate Start signal with
+    /// the address before each byte.
+    ///
+    /// Each byte is not acknowledged by the master. See
+    /// [`I2CMaster::write_bytewise`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The remaining bytes are not read, and the
+    /// bus is released, see [`I2CMaster`].
+    pub async fn read_bytewise<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        addr: u8,
+        index: I,
+    ) -> Result<(), I2CError> {
+        for i in self.slice_bounds(index) {
+            self.read(addr, i..=i).await?;
+        }
+        Ok(())
+    }
+
+    /// Arms a write of the session buffer slice of the range `index` to the
+    /// slave at the address `addr`, and sends the Start signal once `trigger`
+    /// resolves.
+    ///
+    /// The DMA stream and the event handler are fully set up before `trigger`
+    /// is awaited, so that the only work left after the trigger fires is
+    /// setting the CR1.START bit. This keeps the delay between the trigger and
+    /// the Start signal short and stable, which allows to start transfers on
+    /// several buses in lockstep by passing each of them a trigger derived
+    /// from the same event, e.g. a timer interrupt fiber.
+    ///
+    /// The Start signal is generated from software by the task polling the
+    /// returned future. Therefore the achievable jitter is bounded by the
+    /// latency of waking up that task, not by the hardware.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, or the bus is still held by a previous
+    /// operation of this session. A triggered transfer can only begin with a
+    /// fresh Start signal.
+    pub async fn write_triggered<I: SliceIndex<[u8], Output = [u8]>, T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        index: I,
+        trigger: T,
+    ) -> Result<(), I2CError> {
+        self.begin_triggered(addr).await?;
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty triggered transfer");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write { addr, data: self.buf.as_ref()[range.clone()].to_vec() })
+        {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.write_triggered(addr, &buf[range], trigger) })
+            .await;
+        self.check_error()
+    }
+
+    /// Arms a read from the slave at the address `addr` into the session buffer
+    /// slice of the range `index`, and sends the Start signal once `trigger`
+    /// resolves.
+    ///
+    /// See [`I2CMaster::write_triggered`] for details on the triggering
+    /// mechanism.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, or the bus is still held by a previous
+    /// operation of this session. A triggered transfer can only begin with a
+    /// fresh Start signal.
+    pub async fn read_triggered<I: SliceIndex<[u8], Output = [u8]>, T: Future<Output = ()>>(
+        &mut self,
+        addr: u8,
+        index: I,
+        trigger: T,
+    ) -> Result<(), I2CError> {
+        self.begin_triggered(addr).await?;
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty triggered transfer");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| unsafe { drv.read_triggered(addr, &mut buf[range], trigger) })
+            .await;
+        self.check_error()
+    }
+
+    /// Sends the Start signal for the address `addr`, waits `delay` ticks of
+    /// `timer` after the slave acknowledges the address, and then writes the
+    /// data from the session buffer slice of the range `index` to the slave.
+    ///
+    /// Some devices can't accept data immediately after being addressed, e.g.
+    /// slow microcontroller-based slaves, which handle the address in
+    /// firmware, or sensors waking up from a low-power state on their
+    /// address. The delay is made by holding the SCL line low after the
+    /// address acknowledge, which is allowed by the specification for the
+    /// master. Each operation costs `delay` ticks on top of the normal transfer
+    /// time, so use the shortest delay the device needs.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty.
+    pub async fn write_delayed<I: SliceIndex<[u8], Output = [u8]>, T: Timer>(
+        &mut self,
+        addr: u8,
+        index: I,
+        timer: &mut T,
+        delay: u32,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty delayed transfer");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Write { addr, data: self.buf.as_ref()[range.clone()].to_vec() })
+        {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| async move {
+            let pending = unsafe { drv.write_held(addr, &buf[range]).await };
+            if !drv.has_error() {
+                timer.sleep(delay).await;
+                drv.release_addr(addr << 1, pending).await;
+            }
+        })
+        .await;
+        self.check_error()
+    }
+
+    /// Sends the Start signal for the address `addr`, waits `delay` ticks of
+    /// `timer` after the slave acknowledges the address, and then reads the
+    /// data from the slave into the session buffer slice of the range `index`.
+    ///
+    /// See [`I2CMaster::write_delayed`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The bus is released, see [`I2CMaster`].
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty.
+    pub async fn read_delayed<I: SliceIndex<[u8], Output = [u8]>, T: Timer>(
+        &mut self,
         addr: u8,
         index: I,
-    ) -> I2CMaster<'a, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt> {
-        unsafe { self.drv.read(addr, &mut self.buf[index]).await };
-        self
+        timer: &mut T,
+        delay: u32,
+    ) -> Result<(), I2CError> {
+        self.begin(addr).await?;
+        let range = self.slice_bounds(index);
+        assert!(!range.is_empty(), "Empty delayed transfer");
+        #[cfg(feature = "dry-run")]
+        if self.drv.dry_run(I2COp::Read { addr, len: range.len() }) {
+            return Ok(());
+        }
+        self.run_transfer(|drv, buf| async move {
+            let dma_rx = unsafe { drv.read_held(addr, &mut buf[range]).await };
+            if !drv.has_error() {
+                timer.sleep(delay).await;
+                drv.release_addr(addr << 1 | 1, None).await;
+            }
+            dma_rx.await;
+        })
+        .await;
+        self.check_error()
+    }
+
+    /// Returns the PEC calculated by the hardware over the most recent PEC
+    /// transfer, e.g. [`I2CMaster::read_pec`] or [`I2CMaster::write_pec`].
+    ///
+    /// This is the SR2.PEC value captured at the end of the transfer, while the
+    /// PEC calculation is still enabled, so it can be logged or double-checked
+    /// by the application. The value is kept by the driver, so it can come from
+    /// an earlier session. Returns zero if no PEC transfer was performed yet.
+    #[inline]
+    #[must_use]
+    pub fn last_pec(&self) -> u8 {
+        self.drv.last_pec()
+    }
+
+    /// Returns `true` if the most recent operation of this session began with
+    /// a repeated Start signal, and `false` if it began with a fresh Start.
+    ///
+    /// Returns `false` if no operations were performed yet.
+    #[inline]
+    #[must_use]
+    pub fn last_was_repeated_start(&self) -> bool {
+        self.start.is_repeated()
+    }
+
+    /// Sets a timeout for the following operations of the session.
+    ///
+    /// `timeout` is called at the beginning of each operation, and returns a
+    /// future, which resolves when the operation time is up, e.g. a sleep
+    /// future of a timer. If it resolves before the operation completes, the
+    /// driver aborts the transfer: the Stop signal is generated, the
+    /// interrupts are disabled, and the DMA streams are stopped. The
+    /// operation then fails with [`I2CError::Timeout`].
+    ///
+    /// The timeout also bounds the wait for the idle bus before the first
+    /// operation of the session, see [`I2CMaster`], and the wait before a
+    /// restart of a failed operation, see [`I2CMaster::set_retry_backoff`]. If
+    /// another master holds the bus for longer, the operation fails with
+    /// [`I2CError::Timeout`] without generating the Start signal.
+    ///
+    /// The operations waiting for an external event, e.g. the trigger of
+    /// [`I2CMaster::write_triggered`], or the delay of
+    /// [`I2CMaster::write_delayed`], include the wait into the operation time.
+    /// Without a session timeout, the default one of [`I2CDrv::set_timeout`]
+    /// is used.
+    pub fn set_timeout<F: FnMut() -> T + 'a, T: Future<Output = ()> + 'a>(
+        &mut self,
+        mut timeout: F,
+    ) {
+        self.timeout = Some(Box::new(move || Box::pin(timeout())));
+    }
+
+    /// Sets the number of times a failed [`I2CMaster::write`] or
+    /// [`I2CMaster::read`] operation of the session is restarted after a
+    /// recoverable error, see [`I2CError::is_recoverable`].
+    ///
+    /// This lets a multi-step transaction tolerate a bus glitch on one step:
+    /// the driver releases the bus on the error, waits until the bus is idle,
+    /// and restarts just the failed step from a fresh Start signal, leaving
+    /// the other steps of the session alone. Use it for the steps, which are
+    /// self-contained, i.e. don't rely on a preceding step within the same
+    /// Start-Stop frame. If the retries are exhausted, or the error is fatal,
+    /// the operation fails with the error, and the session stays usable, see
+    /// [`I2CMaster`]. The error hook is called for every error.
+    ///
+    /// These retries are independent of the arbitration retries of
+    /// [`I2CDrv::set_arbitration_retries`], which are used up first. The
+    /// default count is zero, which means the errors are returned right away.
+    pub fn set_retries(&mut self, count: u8) {
+        self.retries = count;
+    }
+
+    /// Sets a backoff delay before each restart of a failed operation, see
+    /// [`I2CDrv::set_arbitration_retries`] and [`I2CMaster::set_retries`].
+    ///
+    /// `backoff` is called with the number of the restart, starting from 1,
+    /// and returns a future, which resolves when the delay is over, e.g. a
+    /// sleep future of a timer. This allows a growing or randomized delay, so
+    /// that the competing masters don't collide again. The delay and the
+    /// following wait for the idle bus are bounded by the timeout of
+    /// [`I2CMaster::set_timeout`]. If it resolves first, the operation fails
+    /// with [`I2CError::Timeout`].
+    pub fn set_retry_backoff<F: FnMut(u8) -> T + 'a, T: Future<Output = ()> + 'a>(
+        &mut self,
+        mut backoff: F,
+    ) {
+        self.backoff = Some(Box::new(move |attempt| Box::pin(backoff(attempt))));
+    }
+
+    /// Removes the timeout set with [`I2CMaster::set_timeout`].
+    pub fn clear_timeout(&mut self) {
+        self.timeout = None;
     }
 
     /// Returns a reference to the session buffer.
+    ///
+    /// After a failed operation, the buffer keeps the data transferred before
+    /// the error.
     #[inline]
     #[must_use]
     pub fn buf(&self) -> &[u8] {
-        &self.buf
+        self.abort_leaked();
+        self.buf.as_ref()
     }
 
     /// Returns a mutable reference to the session buffer.
     #[inline]
     #[must_use]
-    pub fn buf_mut(&mut self) -> &mut Box<[u8]> {
+    pub fn buf_mut(&mut self) -> &mut B {
+        self.abort_leaked();
         &mut self.buf
     }
 
+    /// Attaches `buf` to a session created by [`I2CDrv::master_empty`].
+    ///
+    /// # Panics
+    ///
+    /// If the session already has a buffer.
+    pub fn set_buf(&mut self, buf: B) {
+        assert!(!self.has_buf, "Session buffer is already set");
+        self.buf = ManuallyDrop::new(buf);
+        self.has_buf = true;
+    }
+
     /// Sends the Stop signal and returns the session buffer.
+    ///
+    /// The Stop signal is sent only if the session holds the bus, i.e. it's
+    /// not sent after a failed operation, which has already released the bus.
+    /// If no buffer was set, returns an empty one.
     #[must_use]
-    pub fn stop(self) -> Box<[u8]> {
-        let Self { drv, buf } = self;
-        drv.stop();
-        ManuallyDrop::into_inner(buf)
+    pub fn stop(mut self) -> B {
+        self.settle();
+        self.release_bus();
+        let mut session = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut session.buf) }
+    }
+
+    /// Returns the range of the session buffer, which `index` refers to.
+    fn slice_bounds<I: SliceIndex<[u8], Output = [u8]>>(&self, index: I) -> Range<usize> {
+        self.check_buf();
+        let slice = &self.buf.as_ref()[index];
+        let start = slice.as_ptr() as usize - self.buf.as_ref().as_ptr() as usize;
+        start..start + slice.len()
+    }
+
+    /// Runs the transfer started by `transfer` with the driver and the session
+    /// buffer. The transfer is bounded by the timeout, and is aborted if the
+    /// operation future is dropped before completion.
+    async fn run_transfer<'b, F, T>(&'b mut self, transfer: F)
+    where
+        F: FnOnce(
+            &'b mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+            &'b mut [u8],
+        ) -> T,
+        T: Future<Output = ()> + 'b,
+    {
+        let Self { drv, buf, in_flight, timeout, .. } = self;
+        let timeout = start_timeout(timeout, drv);
+        in_flight.set(true);
+        let guard = drv.cancel_guard();
+        let transfer = transfer(drv, (**buf).as_mut());
+        timed(timeout, || guard.abort(I2CError::Timeout), transfer).await;
+        guard.disarm();
+        in_flight.set(false);
+    }
+
+    /// Starts the timeout of the session, or the default one of
+    /// [`I2CDrv::set_timeout`].
+    fn timeout(&mut self) -> Option<Pin<Box<dyn Future<Output = ()> + 'a>>> {
+        start_timeout(&mut self.timeout, self.drv)
+    }
+
+    fn check_buf(&self) {
+        assert!(self.has_buf, "Session buffer is not set");
+    }
+
+    /// Prepares the next operation. The first operation of the session waits
+    /// until no other master holds the bus.
+    async fn begin<A: Into<I2CAddr>>(&mut self, addr: A) -> Result<(), I2CError> {
+        self.settle();
+        self.addr = match addr.into() {
+            I2CAddr::Bit7(addr) => Some(addr),
+            I2CAddr::Bit10(_) => None,
+        };
+        assert!(self.segment.is_none(), "Segmented read is not finished");
+        if !self.start.is_started() {
+            self.bus_idle().await?;
+        }
+        self.start.begin();
+        Ok(())
+    }
+
+    async fn bus_idle(&mut self) -> Result<(), I2CError> {
+        let idle = self.drv.bus_idle();
+        self.bounded(idle).await
+    }
+
+    /// Waits for `wait` outside of a transfer, bounded by the timeout.
+    async fn bounded<T: Future<Output = ()>>(&mut self, wait: T) -> Result<(), I2CError> {
+        let timeout = if let Some(timeout) = self.timeout() {
+            timeout
+        } else {
+            wait.await;
+            return Ok(());
+        };
+        pin_mut!(wait);
+        match future::select(wait, timeout).await {
+            Either::Left(((), _)) => Ok(()),
+            Either::Right(((), _)) => Err(I2CError::Timeout),
+        }
+    }
+
+    fn retries(&self) -> Retries {
+        Retries {
+            arbitration: if self.start.is_repeated() { 0 } else { self.drv.arbitration_retries() },
+            recoverable: self.retries,
+            attempt: 0,
+        }
+    }
+
+    /// Checks whether the last transfer failed with an error, which can be
+    /// restarted, see [`I2CDrv::set_arbitration_retries`] and
+    /// [`I2CMaster::set_retries`]. If so, waits for the backoff delay and the
+    /// idle bus.
+    async fn retry(&mut self, retries: &mut Retries) -> Result<bool, I2CError> {
+        if retries.arbitration > 0 && self.drv.take_arbitration_lost() {
+            retries.arbitration -= 1;
+        } else if retries.recoverable > 0 && self.drv.take_recoverable() {
+            retries.recoverable -= 1;
+            self.start.restart();
+        } else {
+            return Ok(false);
+        }
+        retries.attempt += 1;
+        let backoff = self.backoff.as_mut().map(|backoff| backoff(retries.attempt));
+        let idle = self.drv.bus_idle();
+        let wait = async move {
+            if let Some(backoff) = backoff {
+                backoff.await;
+            }
+            idle.await;
+        };
+        if let Err(err) = self.bounded(wait).await {
+            // the bus is already released by the failed transfer
+            self.start.released();
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Aborts the transfer of an operation future leaked before completion.
+    /// A dropped future has already aborted its transfer, so this only waits
+    /// for the DMA to stop.
+    fn abort_leaked(&self) {
+        if self.in_flight.replace(false) {
+            self.drv.cancel();
+        }
+    }
+
+    /// Accounts an error reported after the last operation, e.g. by an aborted
+    /// transfer of a dropped operation future, or left from an earlier
+    /// session. The bus is already released in this case.
+    fn settle(&mut self) {
+        self.abort_leaked();
+        if self.drv.take_error().is_some() {
+            self.start.released();
+            self.segment = None;
+        }
+    }
+
+    /// Sends the Stop signal if the session holds the bus.
+    fn release_bus(&mut self) {
+        if self.start.stop() {
+            self.drv.stop();
+        }
+    }
+
+    fn check_error(&mut self) -> Result<(), I2CError> {
+        let result = match self.drv.take_error() {
+            Some(err) => {
+                // the bus is already released by the error interrupt handler
+                self.start.released();
+                self.segment = None;
+                Err(err)
+            }
+            None => Ok(()),
+        };
+        match self.addr {
+            Some(addr) => self.drv.track_nacks(addr, result),
+            None => result,
+        }
+    }
+
+    async fn begin_triggered(&mut self, addr: u8) -> Result<(), I2CError> {
+        assert!(
+            !self.start.is_started(),
+            "Triggered transfer while the bus is held by the session"
+        );
+        self.begin(addr).await
+    }
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+    B: AsRef<[u8]> + AsMut<[u8]>,
+> fmt::Debug for I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, B>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("I2CMaster")
+            .field("buf_len", &self.buf.as_ref().len())
+            .field("started", &self.start.is_started())
+            .finish()
+    }
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+    B: AsRef<[u8]> + AsMut<[u8]>,
+> Drop for I2CMaster<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt, B>
+{
+    fn drop(&mut self) {
+        self.settle();
+        self.release_bus();
+        unsafe { ManuallyDrop::drop(&mut self.buf) };
+    }
+}
+
+/// Starts the session `timeout`, or the default one of `drv`.
+fn start_timeout<
+    'a,
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+>(
+    timeout: &mut Option<TimeoutFn<'a>>,
+    drv: &mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+) -> Option<Pin<Box<dyn Future<Output = ()> + 'a>>> {
+    match timeout {
+        Some(timeout) => Some(timeout()),
+        None => drv.default_timeout(),
+    }
+}
+
+/// Runs `transfer` bounded by `timeout`. If the time is up first, aborts the
+/// transfer with `abort`, and waits for the aborted transfer to complete.
+pub(crate) async fn timed<T: Future>(
+    timeout: Option<Pin<Box<dyn Future<Output = ()> + '_>>>,
+    abort: impl FnOnce(),
+    transfer: T,
+) -> T::Output {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return transfer.await,
+    };
+    pin_mut!(transfer);
+    match future::select(transfer, timeout).await {
+        Either::Left((output, _)) => output,
+        Either::Right(((), transfer)) => {
+            abort();
+            // the aborted transfer completes right away
+            transfer.await
+        }
     }
 }
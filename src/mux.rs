@@ -0,0 +1,180 @@
+use crate::{I2CDrv, I2CError};
+use drone_cortexm::thr::prelude::*;
+use drone_stm32_map::periph::{dma::ch::DmaChMap, i2c::I2CMap};
+
+/// I²C bus multiplexer with a single channel-select register.
+///
+/// Models TCA9548A-like switches, which are controlled by writing a single
+/// byte to the multiplexer address, where bit `n` connects the downstream
+/// channel `n`. The multiplexer object tracks the currently active channel, so
+/// that selecting an already active channel doesn't touch the bus.
+///
+/// A typical pattern is to select the channel of the downstream device right
+/// before each transaction with it:
+///
+/// ```no_run
+/// # #![feature(const_fn_fn_ptr_basics)]
+/// # use drone_stm32_map::periph::{
+/// #     dma::ch::{Dma1Ch5, Dma1Ch6},
+/// #     i2c::I2C1,
+/// # };
+/// # mod thr {
+/// #     use drone_stm32_map::thr::*;
+/// #     drone_cortexm::thr::nvic! {
+/// #         thread => pub Thr {};
+/// #         local => pub ThrLocal {};
+/// #         vtable => pub Vtable;
+/// #         index => pub Thrs;
+/// #         init => pub ThrsInit;
+/// #         threads => {
+/// #             interrupts => {
+/// #                 16: pub dma1_ch5;
+/// #                 17: pub dma1_ch6;
+/// #                 31: pub i2c1_ev;
+/// #                 32: pub i2c1_er;
+/// #             };
+/// #         };
+/// #     }
+/// # }
+/// # async fn handler() -> Result<(), smartoris_i2c::I2CError> {
+/// # let mut i2c1: smartoris_i2c::I2CDrv<
+/// #     I2C1,
+/// #     thr::I2C1Ev,
+/// #     thr::I2C1Er,
+/// #     Dma1Ch6,
+/// #     thr::Dma1Ch6,
+/// #     Dma1Ch5,
+/// #     thr::Dma1Ch5,
+/// # > = unsafe { core::mem::MaybeUninit::uninit().assume_init() };
+/// use smartoris_i2c::I2CMux;
+///
+/// let mut mux = I2CMux::new(0x70);
+/// let mut buf = vec![0x92, 0, 0, 0].into_boxed_slice();
+/// for _ in 0..10 {
+///     mux.select(&mut i2c1, 3).await?; // touches the bus only the first time
+///     let mut master = i2c1.master(buf);
+///     master.write(0x39, ..1).await?;
+///     master.read(0x39, ..).await?;
+///     buf = master.stop();
+/// }
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+pub struct I2CMux {
+    addr: u8,
+    channel: Option<u8>,
+}
+
+impl I2CMux {
+    /// Number of downstream channels.
+    pub const CHANNELS: u8 = 8;
+
+    /// Creates a new multiplexer at the address `addr`.
+    ///
+    /// The active channel is considered unknown until the first
+    /// [`select`](I2CMux::select) or [`deselect`](I2CMux::deselect) call.
+    #[must_use]
+    pub const fn new(addr: u8) -> Self {
+        Self { addr, channel: None }
+    }
+
+    /// Returns the address of the multiplexer.
+    #[inline]
+    #[must_use]
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    /// Returns the currently active channel, if known.
+    #[inline]
+    #[must_use]
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    /// Connects the downstream `channel`, disconnecting all others.
+    ///
+    /// Does nothing if `channel` is already active.
+    ///
+    /// # Errors
+    ///
+    /// If the multiplexer doesn't respond. The active channel is kept as it
+    /// was.
+    ///
+    /// # Panics
+    ///
+    /// If `channel` is not less than [`I2CMux::CHANNELS`].
+    pub async fn select<
+        I2C: I2CMap,
+        I2CEv: IntToken,
+        I2CEr: IntToken,
+        DmaTx: DmaChMap,
+        DmaTxInt: IntToken,
+        DmaRx: DmaChMap,
+        DmaRxInt: IntToken,
+    >(
+        &mut self,
+        drv: &mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+        channel: u8,
+    ) -> Result<(), I2CError> {
+        assert!(channel < Self::CHANNELS, "Multiplexer channel out of range");
+        if self.channel != Some(channel) {
+            self.write_control(drv, 1 << channel).await?;
+            self.channel = Some(channel);
+        }
+        Ok(())
+    }
+
+    /// Disconnects all downstream channels.
+    ///
+    /// # Errors
+    ///
+    /// If the multiplexer doesn't respond. The active channel becomes
+    /// unknown.
+    pub async fn deselect<
+        I2C: I2CMap,
+        I2CEv: IntToken,
+        I2CEr: IntToken,
+        DmaTx: DmaChMap,
+        DmaTxInt: IntToken,
+        DmaRx: DmaChMap,
+        DmaRxInt: IntToken,
+    >(
+        &mut self,
+        drv: &mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+    ) -> Result<(), I2CError> {
+        self.channel = None;
+        self.write_control(drv, 0).await
+    }
+
+    /// Forgets the currently active channel.
+    ///
+    /// Should be called when the multiplexer state could have been changed
+    /// externally, e.g. after resetting it through its reset pin. The next
+    /// [`select`](I2CMux::select) call will then unconditionally write the
+    /// channel-select register.
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.channel = None;
+    }
+
+    async fn write_control<
+        I2C: I2CMap,
+        I2CEv: IntToken,
+        I2CEr: IntToken,
+        DmaTx: DmaChMap,
+        DmaTxInt: IntToken,
+        DmaRx: DmaChMap,
+        DmaRxInt: IntToken,
+    >(
+        &self,
+        drv: &mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+        control: u8,
+    ) -> Result<(), I2CError> {
+        let mut master = drv.master(vec![control].into_boxed_slice());
+        master.write(self.addr, ..).await?;
+        drop(master.stop());
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use crate::I2CError;
+
+/// Number of slave addresses tracked at the same time.
+const NACK_SLOTS: usize = 8;
+
+/// Counts of consecutive NACKs per slave address.
+///
+/// See [`I2CDrv::set_nack_threshold`](crate::I2CDrv::set_nack_threshold).
+pub(crate) struct NackCounts {
+    threshold: u8,
+    // (address, count) pairs, the zero count marks a free slot
+    slots: [(u8, u8); NACK_SLOTS],
+}
+
+impl NackCounts {
+    pub(crate) const fn new() -> Self {
+        Self { threshold: 0, slots: [(0, 0); NACK_SLOTS] }
+    }
+
+    pub(crate) fn set_threshold(&mut self, threshold: u8) {
+        self.threshold = threshold;
+        self.reset();
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.slots = [(0, 0); NACK_SLOTS];
+    }
+
+    /// Accounts the `result` of an operation with the slave at the 7-bit
+    /// address `addr`, and turns [`I2CError::Nack`] into
+    /// [`I2CError::DeviceGone`] once the threshold is reached.
+    pub(crate) fn track(&mut self, addr: u8, result: Result<(), I2CError>) -> Result<(), I2CError> {
+        if self.threshold == 0 {
+            return result;
+        }
+        match result {
+            Err(I2CError::Nack(_)) => {
+                if self.nack(addr) >= self.threshold {
+                    return Err(I2CError::DeviceGone);
+                }
+            }
+            Err(_) => {}
+            Ok(()) => {
+                if let Some(slot) = self.slots.iter_mut().find(|(a, n)| *n > 0 && *a == addr) {
+                    *slot = (0, 0);
+                }
+            }
+        }
+        result
+    }
+
+    fn nack(&mut self, addr: u8) -> u8 {
+        let index = self
+            .slots
+            .iter()
+            .position(|&(a, n)| n > 0 && a == addr)
+            .or_else(|| self.slots.iter().position(|&(_, n)| n == 0))
+            .unwrap_or_else(|| {
+                // all slots are taken, forget the address with the fewest NACKs
+                (0..NACK_SLOTS).min_by_key(|&i| self.slots[i].1).unwrap_or(0)
+            });
+        let (a, n) = &mut self.slots[index];
+        if *a != addr {
+            *a = addr;
+            *n = 0;
+        }
+        *n = n.saturating_add(1);
+        *n
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{I2CDrv, I2CError};
+use core::{cell::Cell, mem::ManuallyDrop, slice::SliceIndex};
+use drone_cortexm::thr::prelude::*;
+use drone_stm32_map::periph::{dma::ch::DmaChMap, i2c::I2CMap};
+
+/// Request of a remote master addressing the slave.
+///
+/// Returned by [`I2CSlave::listen`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct I2CSlaveReq {
+    /// `true` if the master reads from the slave, which should respond with
+    /// [`I2CSlave::transmit`]. `false` if the master writes to the slave,
+    /// which should respond with [`I2CSlave::receive`].
+    pub read: bool,
+    /// `true` if the dual address from OAR2 is matched, `false` if the
+    /// primary address from OAR1 is matched.
+    pub dual: bool,
+    /// `true` if the general call address 0x00 is matched, instead of an own
+    /// address. General call requests are always writes, and are received
+    /// only if enabled with [`I2CSlave::enable_general_call`].
+    pub general_call: bool,
+}
+
+/// I²C slave session.
+///
+/// The session object takes ownership of the provided buffer, which is returned
+/// by [`I2CSlave::stop`] method. If the session is dropped without calling the
+/// `stop` method, the slave is disabled and the buffer is freed.
+///
+/// The session serves the requests of a remote master in a loop: each
+/// [`I2CSlave::listen`] call waits for the own address to be matched, and the
+/// following [`I2CSlave::receive`] or [`I2CSlave::transmit`] call transfers
+/// the data phase through DMA. [`I2CSlave::serve_rx`] and
+/// [`I2CSlave::serve_tx`] transfer it byte by byte from the event interrupt
+/// handler instead. SCL is stretched by the peripheral between the
+/// calls, so the master waits for the slave to respond.
+///
+/// If an operation future is dropped before completion, e.g. cancelled by a
+/// timeout, its transfer is aborted: the DMA streams are stopped, and the SCL
+/// and SDA lines are released. The slave keeps listening. The transfer of an
+/// operation future leaked before completion, e.g. with [`core::mem::forget`],
+/// is aborted the same way by the next session call, or when the session is
+/// dropped.
+pub struct I2CSlave<
+    'a,
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> {
+    drv: &'a mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+    buf: ManuallyDrop<Box<[u8]>>,
+    in_flight: Cell<bool>,
+}
+
+impl<
+    'a,
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> I2CSlave<'a, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    pub(crate) fn new(
+        drv: &'a mut I2CDrv<I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>,
+        buf: Box<[u8]>,
+    ) -> Self {
+        Self { drv, buf: ManuallyDrop::new(buf), in_flight: Cell::new(false) }
+    }
+
+    /// Waits for a remote master to address the slave, and returns the
+    /// request.
+    ///
+    /// A Stop condition left from the previous request is cleared. The
+    /// address phase is completed by the time this method returns, and SCL is
+    /// stretched until the data phase is started with [`I2CSlave::receive`]
+    /// or [`I2CSlave::transmit`].
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The slave keeps listening.
+    pub async fn listen(&mut self) -> Result<I2CSlaveReq, I2CError> {
+        self.begin();
+        let req = self.drv.slave_listen().await;
+        self.drv.take_error().map_or(Ok(req), Err)
+    }
+
+    /// Receives the data written by the master into the session buffer slice
+    /// of the range `index`, and returns the number of bytes received.
+    ///
+    /// Completes when the master sends a Stop condition, or a repeated Start
+    /// condition, which is left pending for the next [`I2CSlave::listen`]
+    /// call. If the master writes more bytes than the slice holds, the extra
+    /// bytes are acknowledged and discarded.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The slave keeps listening.
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, or exceeds the DMA transfer size limit.
+    pub async fn receive<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        index: I,
+    ) -> Result<usize, I2CError> {
+        self.begin();
+        let buf_rx = &mut self.buf[index];
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        let count = unsafe { self.drv.slave_receive(buf_rx).await };
+        guard.disarm();
+        self.in_flight.set(false);
+        self.drv.take_error().map_or(Ok(count), Err)
+    }
+
+    /// Transmits the data from the session buffer slice of the range `index`
+    /// to the master reading from the slave.
+    ///
+    /// Completes when the master doesn't acknowledge a byte, which marks the
+    /// end of the read, or sends a Stop or a repeated Start condition. If the
+    /// master reads more bytes than the slice holds, 0xFF bytes are sent.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The slave keeps listening.
+    ///
+    /// # Panics
+    ///
+    /// If the range `index` is empty, or exceeds the DMA transfer size limit.
+    pub async fn transmit<I: SliceIndex<[u8], Output = [u8]>>(
+        &mut self,
+        index: I,
+    ) -> Result<(), I2CError> {
+        self.begin();
+        let buf_tx = &self.buf[index];
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        unsafe { self.drv.slave_transmit(buf_tx).await };
+        guard.disarm();
+        self.in_flight.set(false);
+        match self.drv.take_error() {
+            // the master doesn't acknowledge the last byte it reads
+            None | Some(I2CError::Nack(_)) => Ok(()),
+            Some(err) => Err(err),
+        }
+    }
+
+    /// Receives the data written by the master byte by byte, passing each byte
+    /// with its index to `serve`, and returns the number of bytes received.
+    ///
+    /// This is an alternative to [`I2CSlave::receive`] for the slaves, which
+    /// process the data on the fly, e.g. decode a command while it arrives.
+    /// `serve` is called from the event interrupt handler on each SR1.RXNE
+    /// event. Completes the same way as [`I2CSlave::receive`].
+    ///
+    /// The peripheral stretches SCL while a received byte is not read, so the
+    /// master waits for `serve` to return. The timing budget is the clock
+    /// stretching the master tolerates, and 25 ms in the SMBus mode, after
+    /// which the transfer fails with [`I2CError::Timeout`]. Clock stretching
+    /// is never disabled by the driver, so a slow `serve` can't cause
+    /// [`I2CError::Overrun`], but it slows down the whole bus.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The slave keeps listening.
+    pub async fn serve_rx<F: FnMut(usize, u8) + Send + 'static>(
+        &mut self,
+        serve: F,
+    ) -> Result<usize, I2CError> {
+        self.begin();
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        let count = self.drv.slave_serve_rx(serve).await;
+        guard.disarm();
+        self.in_flight.set(false);
+        self.drv.take_error().map_or(Ok(count), Err)
+    }
+
+    /// Transmits the data to the master reading from the slave byte by byte,
+    /// taking each byte from `serve` called with the byte index, and returns
+    /// the number of bytes loaded into the data register.
+    ///
+    /// This is an alternative to [`I2CSlave::transmit`] for the slaves, which
+    /// produce the data on the fly, e.g. sample a sensor. `serve` is called
+    /// from the event interrupt handler on each SR1.TXE event. Completes the
+    /// same way as [`I2CSlave::transmit`]. The count includes the last byte
+    /// loaded before the master ends the read, which may be not transmitted.
+    ///
+    /// The peripheral stretches SCL while the data register is empty, so the
+    /// master waits for `serve` to return. The timing budget is the same as
+    /// for [`I2CSlave::serve_rx`]: a slow `serve` can't cause
+    /// [`I2CError::Overrun`], but it slows down the whole bus, and in the
+    /// SMBus mode fails with [`I2CError::Timeout`] after 25 ms.
+    ///
+    /// # Errors
+    ///
+    /// If a bus error occurs. The slave keeps listening.
+    pub async fn serve_tx<F: FnMut(usize) -> u8 + Send + 'static>(
+        &mut self,
+        serve: F,
+    ) -> Result<usize, I2CError> {
+        self.begin();
+        self.in_flight.set(true);
+        let guard = self.drv.cancel_guard();
+        let count = self.drv.slave_serve_tx(serve).await;
+        guard.disarm();
+        self.in_flight.set(false);
+        match self.drv.take_error() {
+            // the master doesn't acknowledge the last byte it reads
+            None | Some(I2CError::Nack(_)) => Ok(count),
+            Some(err) => Err(err),
+        }
+    }
+
+    /// Enables the general call address 0x00 reception.
+    ///
+    /// General call requests are acknowledged and returned by
+    /// [`I2CSlave::listen`] with [`I2CSlaveReq::general_call`] set, so that
+    /// the broadcast data can be told from the data addressed to this slave.
+    /// The general call is disabled with the slave.
+    pub fn enable_general_call(&mut self) {
+        self.drv.set_general_call(true);
+    }
+
+    /// Disables the general call address 0x00 reception.
+    pub fn disable_general_call(&mut self) {
+        self.drv.set_general_call(false);
+    }
+
+    /// Returns a reference to the session buffer.
+    #[inline]
+    #[must_use]
+    pub fn buf(&self) -> &[u8] {
+        self.abort_leaked();
+        &self.buf
+    }
+
+    /// Returns a mutable reference to the session buffer.
+    #[inline]
+    #[must_use]
+    pub fn buf_mut(&mut self) -> &mut Box<[u8]> {
+        self.abort_leaked();
+        &mut self.buf
+    }
+
+    /// Disables the slave, so that the own addresses are no longer
+    /// acknowledged, and returns the session buffer.
+    #[must_use]
+    pub fn stop(self) -> Box<[u8]> {
+        let mut session = ManuallyDrop::new(self);
+        session.abort_leaked();
+        session.drv.slave_disable();
+        unsafe { ManuallyDrop::take(&mut session.buf) }
+    }
+
+    fn begin(&mut self) {
+        self.abort_leaked();
+        self.drv.clear_error();
+    }
+
+    /// Aborts the transfer of an operation future leaked before completion.
+    fn abort_leaked(&self) {
+        if self.in_flight.replace(false) {
+            self.drv.cancel();
+        }
+    }
+}
+
+impl<
+    I2C: I2CMap,
+    I2CEv: IntToken,
+    I2CEr: IntToken,
+    DmaTx: DmaChMap,
+    DmaTxInt: IntToken,
+    DmaRx: DmaChMap,
+    DmaRxInt: IntToken,
+> Drop for I2CSlave<'_, I2C, I2CEv, I2CEr, DmaTx, DmaTxInt, DmaRx, DmaRxInt>
+{
+    fn drop(&mut self) {
+        self.abort_leaked();
+        self.drv.slave_disable();
+        unsafe { ManuallyDrop::drop(&mut self.buf) };
+    }
+}
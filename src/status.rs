@@ -0,0 +1,91 @@
+use crate::diverged::I2CDiverged;
+use drone_cortexm::reg::prelude::*;
+use drone_stm32_map::periph::i2c::{traits::*, I2CMap};
+
+/// Snapshot of the I²C status registers.
+///
+/// Returned by [`I2CDrv::status`](crate::I2CDrv::status) for diagnostic
+/// purposes. SR2 is read before SR1, so that taking the snapshot doesn't clear
+/// the ADDR flag.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct I2CStatus {
+    /// Start condition generated (SR1.SB).
+    pub sb: bool,
+    /// Address sent in master mode, or matched in slave mode (SR1.ADDR).
+    pub addr: bool,
+    /// Byte transfer finished (SR1.BTF).
+    pub btf: bool,
+    /// 10-bit header sent (SR1.ADD10).
+    pub add10: bool,
+    /// Stop condition detected in slave mode (SR1.STOPF).
+    pub stopf: bool,
+    /// Data register not empty (SR1.RxNE).
+    pub rxne: bool,
+    /// Data register empty (SR1.TxE).
+    pub txe: bool,
+    /// Misplaced Start or Stop condition (SR1.BERR).
+    pub berr: bool,
+    /// Arbitration lost (SR1.ARLO).
+    pub arlo: bool,
+    /// Acknowledge failure (SR1.AF).
+    pub af: bool,
+    /// Overrun or underrun (SR1.OVR).
+    pub ovr: bool,
+    /// PEC error in reception (SR1.PECERR).
+    pub pecerr: bool,
+    /// Timeout or Tlow error (SR1.TIMEOUT).
+    pub timeout: bool,
+    /// SMBus alert (SR1.SMBALERT).
+    pub smbalert: bool,
+    /// Master mode (SR2.MSL).
+    pub msl: bool,
+    /// Bus busy (SR2.BUSY).
+    pub busy: bool,
+    /// Transmitter (SR2.TRA).
+    pub tra: bool,
+    /// General call address received in slave mode (SR2.GENCALL).
+    pub gencall: bool,
+    /// SMBus device default address received in slave mode (SR2.SMBDEFAULT).
+    pub smbdefault: bool,
+    /// SMBus host header received in slave mode (SR2.SMBHOST).
+    pub smbhost: bool,
+    /// Dual address matched in slave mode (SR2.DUALF).
+    pub dualf: bool,
+    /// Packet error checking register (SR2.PEC).
+    pub pec: u8,
+}
+
+impl I2CStatus {
+    pub(crate) fn load<I2C: I2CMap>(i2c: &I2CDiverged<I2C>) -> Self {
+        // SR2 is read before SR1, because reading SR2 after SR1 clears ADDR
+        let sr2 = i2c.i2c_sr2.load_val();
+        let sr1 = i2c.i2c_sr1.load_val();
+        let i2c_sr1 = i2c.i2c_sr1;
+        let i2c_sr2 = i2c.i2c_sr2;
+        Self {
+            sb: i2c_sr1.sb().read(&sr1),
+            addr: i2c_sr1.addr().read(&sr1),
+            btf: i2c_sr1.btf().read(&sr1),
+            add10: i2c_sr1.add10().read(&sr1),
+            stopf: i2c_sr1.stopf().read(&sr1),
+            rxne: i2c_sr1.rx_ne().read(&sr1),
+            txe: i2c_sr1.tx_e().read(&sr1),
+            berr: i2c_sr1.berr().read(&sr1),
+            arlo: i2c_sr1.arlo().read(&sr1),
+            af: i2c_sr1.af().read(&sr1),
+            ovr: i2c_sr1.ovr().read(&sr1),
+            pecerr: i2c_sr1.pecerr().read(&sr1),
+            timeout: i2c_sr1.timeout().read(&sr1),
+            smbalert: i2c_sr1.smbalert().read(&sr1),
+            msl: i2c_sr2.msl().read(&sr2),
+            busy: i2c_sr2.busy().read(&sr2),
+            tra: i2c_sr2.tra().read(&sr2),
+            gencall: i2c_sr2.gencall().read(&sr2),
+            smbdefault: i2c_sr2.smbdefault().read(&sr2),
+            smbhost: i2c_sr2.smbhost().read(&sr2),
+            dualf: i2c_sr2.dualf().read(&sr2),
+            pec: i2c_sr2.pec().read(&sr2) as u8,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{I2CConfig, I2CMode};
+
+/// SCL timing computed from the driver configuration.
+///
+/// All times are in nanoseconds. The values are derived from the register
+/// settings only, and don't account for the actual rise and fall times of the
+/// bus, which extend the SCL period.
+///
+/// The limits are from the I²C-bus specification (UM10204), table "Characteristics
+/// of the SDA and SCL bus lines":
+///
+/// | Parameter                | Standard-mode | Fast-mode |
+/// |--------------------------|---------------|-----------|
+/// | f<sub>SCL</sub> max      | 100 kHz       | 400 kHz   |
+/// | t<sub>LOW</sub> min      | 4700 ns       | 1300 ns   |
+/// | t<sub>HIGH</sub> min     | 4000 ns       | 600 ns    |
+/// | t<sub>r</sub> max        | 1000 ns       | 300 ns    |
+/// | C<sub>b</sub> max        | 400 pF        | 400 pF    |
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy)]
+pub struct I2CTimingReport {
+    /// SCL frequency in Hz: f<sub>PCLK</sub> ÷ (CCR × (`low` + `high`)), where
+    /// `low` and `high` are from [`I2CMode::duty_ratio`].
+    pub scl_frequency: u32,
+    /// SCL low period: `low` × CCR × T<sub>PCLK</sub>.
+    pub t_low: u32,
+    /// SCL high period: `high` × CCR × T<sub>PCLK</sub>.
+    pub t_high: u32,
+    /// Maximum rise time allowed by the TRISE register: (TRISE - 1) ×
+    /// T<sub>PCLK</sub>.
+    pub t_r_configured: u32,
+    /// Maximum rise time allowed by the specification for the mode.
+    pub t_r_spec: u32,
+    /// Maximum pull-up resistance in ohms, which meets
+    /// [`t_r_spec`](I2CTimingReport::t_r_spec) with the given bus capacitance
+    /// C<sub>b</sub>: t<sub>r</sub> ÷ (0.8473 × C<sub>b</sub>). The 0.8473
+    /// factor is ln(0.7 ÷ 0.3) for the rise from 0.3 V<sub>DD</sub> to 0.7
+    /// V<sub>DD</sub>.
+    pub r_p_max: u32,
+    /// The SCL frequency exceeds the mode limit.
+    pub frequency_violation: bool,
+    /// The SCL low period is shorter than the mode minimum.
+    pub t_low_violation: bool,
+    /// The SCL high period is shorter than the mode minimum.
+    pub t_high_violation: bool,
+    /// The TRISE register allows a longer rise time than the mode maximum.
+    pub t_r_violation: bool,
+    /// The bus capacitance exceeds the specification maximum.
+    pub capacitance_violation: bool,
+}
+
+impl I2CTimingReport {
+    pub(crate) fn new(config: &I2CConfig, pclk_hz: u32, bus_cap_pf: u32) -> Self {
+        let (low, high) = config.i2c_mode.duty_ratio();
+        let (f_max, t_low_min, t_high_min, t_r_spec) = match config.i2c_mode {
+            I2CMode::Sm1 => (100_000, 4700, 4000, 1000),
+            I2CMode::Fm2 | I2CMode::Fm169 => (400_000, 1300, 600, 300),
+        };
+        let ns = |cycles: u64| (cycles * 1_000_000_000 / u64::from(pclk_hz)) as u32;
+        let ccr = u64::from(config.i2c_presc);
+        let scl_frequency = (u64::from(pclk_hz) / (ccr * u64::from(low + high)).max(1)) as u32;
+        let t_low = ns(u64::from(low) * ccr);
+        let t_high = ns(u64::from(high) * ccr);
+        let t_r_configured = ns(u64::from(config.i2c_trise.saturating_sub(1)));
+        let r_p_max =
+            (u64::from(t_r_spec) * 1_000_000 / (847 * u64::from(bus_cap_pf)).max(1)) as u32;
+        Self {
+            scl_frequency,
+            t_low,
+            t_high,
+            t_r_configured,
+            t_r_spec,
+            r_p_max,
+            frequency_violation: scl_frequency > f_max,
+            t_low_violation: t_low < t_low_min,
+            t_high_violation: t_high < t_high_min,
+            t_r_violation: t_r_configured > t_r_spec,
+            capacitance_violation: bus_cap_pf > 400,
+        }
+    }
+
+    /// Returns `true` if any of the checked parameters violates the
+    /// specification.
+    #[must_use]
+    pub fn has_violations(&self) -> bool {
+        self.frequency_violation
+            || self.t_low_violation
+            || self.t_high_violation
+            || self.t_r_violation
+            || self.capacitance_violation
+    }
+}
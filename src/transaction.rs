@@ -0,0 +1,13 @@
+use core::ops::Range;
+
+/// Operation of a combined transaction.
+///
+/// Accepted by [`I2CMaster::transaction`](crate::I2CMaster::transaction). The
+/// ranges index the session buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum I2CTransactionOp {
+    /// Write of the session buffer slice of the range to the slave.
+    Write(Range<usize>),
+    /// Read from the slave into the session buffer slice of the range.
+    Read(Range<usize>),
+}